@@ -5,7 +5,7 @@ use cw2::set_contract_version;
 
 use crate::error::ContractError;
 use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{ClaimInfo, State, CLAIM_INFO, STATE, USER_INFO};
+use crate::state::{ClaimInfo, Roles, State, CLAIM_INFO, ROLES, STATE, USER_INFO};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:moon-claim";
@@ -25,6 +25,16 @@ pub fn instantiate(
     };
     STATE.save(deps.storage, &state)?;
 
+    // all roles start with the owner
+    ROLES.save(
+        deps.storage,
+        &Roles {
+            operator: info.sender.clone(),
+            treasury: info.sender.clone(),
+            pauser: info.sender.clone(),
+        },
+    )?;
+
     CLAIM_INFO.save(deps.storage, &msg.claim_info)?;
 
     Ok(Response::new()
@@ -41,58 +51,148 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::TransferOwnership { address } => {
-            execute::transfer_ownership(deps, info, address)
+        ExecuteMsg::TransferOwnership { address, expiry } => {
+            execute::transfer_ownership(deps, _env, info, address, expiry)
         }
+        ExecuteMsg::AcceptOwnership {} => execute::accept_ownership(deps, _env, info),
+        ExecuteMsg::CancelOwnershipTransfer {} => execute::cancel_ownership_transfer(deps, info),
+        ExecuteMsg::UpdateRoles {
+            operator,
+            treasury,
+            pauser,
+        } => execute::update_roles(deps, info, operator, treasury, pauser),
         ExecuteMsg::TogglePause {} => execute::toggle_pause(deps, info),
         ExecuteMsg::UpdateClaimInfo { claim_info } => {
             execute::update_claim_info(deps, info, claim_info)
         }
         ExecuteMsg::Claim {} => execute::claim(deps, _env, info),
         ExecuteMsg::SetUsers { users } => execute::set_users(deps, info, users),
-        ExecuteMsg::Withdraw {
-            address,
-            denom,
-            amount,
-        } => execute::withdraw(deps, info, address, denom, amount),
+        ExecuteMsg::Withdraw { denom, amount } => execute::withdraw(deps, info, denom, amount),
     }
 }
 
 pub mod execute {
     use cosmwasm_std::{coins, BankMsg};
+    use cw_utils::Expiration;
 
     use crate::{
         msg::UserInfoItem,
-        state::{UserInfo, USER_INFO},
+        state::{PendingOwner, UserInfo, PENDING_OWNER, USER_INFO},
     };
 
     use super::*;
 
     pub fn transfer_ownership(
         deps: DepsMut,
+        env: Env,
         info: MessageInfo,
         address: String,
+        expiry: Option<Expiration>,
     ) -> Result<Response, ContractError> {
         // only owner
         let state = STATE.load(deps.storage)?;
         state.check_owner(info.sender.clone())?;
 
+        let expires = expiry.unwrap_or_default();
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+
+        // takes effect once the new owner accepts
         let new_owner = deps.api.addr_validate(&address)?;
-        STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
-            state.owner = new_owner.clone();
-            Ok(state)
-        })?;
+        PENDING_OWNER.save(
+            deps.storage,
+            &PendingOwner {
+                address: new_owner.clone(),
+                expires,
+            },
+        )?;
 
         Ok(Response::new()
             .add_attribute("action", "transfer_ownership")
             .add_attribute("from", info.sender.clone())
-            .add_attribute("to", new_owner))
+            .add_attribute("to", new_owner)
+            .add_attribute("expires", expires.to_string()))
+    }
+
+    pub fn accept_ownership(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pending = PENDING_OWNER
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoPendingOwner {})?;
+
+        // only pending owner
+        if info.sender != pending.address {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        if pending.expires.is_expired(&env.block) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+
+        let state = STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
+            state.owner = pending.address.clone();
+            Ok(state)
+        })?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("action", "accept_ownership")
+            .add_attribute("owner", state.owner))
+    }
+
+    pub fn cancel_ownership_transfer(
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        // only owner
+        let state = STATE.load(deps.storage)?;
+        state.check_owner(info.sender)?;
+
+        if !PENDING_OWNER.exists(deps.storage) {
+            return Err(ContractError::NoPendingOwner {});
+        }
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new().add_attribute("action", "cancel_ownership_transfer"))
+    }
+
+    pub fn update_roles(
+        deps: DepsMut,
+        info: MessageInfo,
+        operator: Option<String>,
+        treasury: Option<String>,
+        pauser: Option<String>,
+    ) -> Result<Response, ContractError> {
+        // only owner
+        let state = STATE.load(deps.storage)?;
+        state.check_owner(info.sender)?;
+
+        let mut roles = ROLES.load(deps.storage)?;
+        if let Some(operator) = operator {
+            roles.operator = deps.api.addr_validate(&operator)?;
+        }
+        if let Some(treasury) = treasury {
+            roles.treasury = deps.api.addr_validate(&treasury)?;
+        }
+        if let Some(pauser) = pauser {
+            roles.pauser = deps.api.addr_validate(&pauser)?;
+        }
+        ROLES.save(deps.storage, &roles)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_roles")
+            .add_attribute("operator", roles.operator)
+            .add_attribute("treasury", roles.treasury)
+            .add_attribute("pauser", roles.pauser))
     }
 
     pub fn withdraw(
         deps: DepsMut,
         info: MessageInfo,
-        to: String,
         denom: String,
         amount: u128,
     ) -> Result<Response, ContractError> {
@@ -100,21 +200,22 @@ pub mod execute {
         let state = STATE.load(deps.storage)?;
         state.check_owner(info.sender.clone())?;
 
-        let to = deps.api.addr_validate(&to)?;
+        let treasury = ROLES.load(deps.storage)?.treasury;
         let transfer = BankMsg::Send {
-            to_address: to.to_string(),
+            to_address: treasury.to_string(),
             amount: coins(amount, denom),
         };
 
         Ok(Response::new()
             .add_message(transfer)
-            .add_attribute("action", "withdraw"))
+            .add_attribute("action", "withdraw")
+            .add_attribute("to", treasury))
     }
 
     pub fn toggle_pause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
-        // only owner
-        let state = STATE.load(deps.storage)?;
-        state.check_owner(info.sender)?;
+        // only pauser
+        let roles = ROLES.load(deps.storage)?;
+        roles.check_pauser(info.sender)?;
 
         let new_state = STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
             state.paused = !state.paused;
@@ -179,9 +280,9 @@ pub mod execute {
         info: MessageInfo,
         users: Vec<UserInfoItem>,
     ) -> Result<Response, ContractError> {
-        // only owner
-        let state = STATE.load(deps.storage)?;
-        state.check_owner(info.sender)?;
+        // only operator
+        let roles = ROLES.load(deps.storage)?;
+        roles.check_operator(info.sender)?;
 
         for user in users {
             let addr = deps.api.addr_validate(&user.address)?;
@@ -212,12 +313,29 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             to_json_binary(&USER_INFO.load(deps.storage, &deps.api.addr_validate(&address)?)?)
         }
         QueryMsg::GetUsers {} => to_json_binary(&query::get_users(deps)?),
+        QueryMsg::GetRoles {} => to_json_binary(&query::get_roles(deps)?),
     }
 }
 
 pub mod query {
     use super::*;
-    use crate::{msg::UserInfoItem, state::USER_INFO};
+    use crate::{
+        msg::{GetRolesResponse, UserInfoItem},
+        state::{PENDING_OWNER, USER_INFO},
+    };
+
+    pub fn get_roles(deps: Deps) -> StdResult<GetRolesResponse> {
+        let state = STATE.load(deps.storage)?;
+        let roles = ROLES.load(deps.storage)?;
+
+        Ok(GetRolesResponse {
+            owner: state.owner,
+            pending_owner: PENDING_OWNER.may_load(deps.storage)?,
+            operator: roles.operator,
+            treasury: roles.treasury,
+            pauser: roles.pauser,
+        })
+    }
 
     pub fn get_users(deps: Deps) -> StdResult<Vec<UserInfoItem>> {
         let users: Vec<UserInfoItem> = USER_INFO
@@ -11,4 +11,10 @@ pub enum ContractError {
 
     #[error("NotActive")]
     NotActive {},
+
+    #[error("NoPendingOwner")]
+    NoPendingOwner {},
+
+    #[error("OwnershipTransferExpired")]
+    OwnershipTransferExpired {},
 }
@@ -87,18 +87,13 @@ impl MoonClaimContract {
         &self,
         app: &mut App,
         sender: &Addr,
-        address: String,
         denom: String,
         amount: u128,
     ) -> Result<(), ContractError> {
         app.execute_contract(
             sender.clone(),
             self.0.clone(),
-            &ExecuteMsg::Withdraw {
-                address,
-                denom,
-                amount,
-            },
+            &ExecuteMsg::Withdraw { denom, amount },
             &[],
         )
         .map_err(|err| {
@@ -101,13 +101,7 @@ pub fn withdraw() {
     MoonClaimContract::mint_coins(&mut app, &contract.0, coins(1000000, REWARD_DENOM));
 
     contract
-        .withdraw(
-            &mut app,
-            &owner,
-            owner.to_string(),
-            REWARD_DENOM.to_string(),
-            900000,
-        )
+        .withdraw(&mut app, &owner, REWARD_DENOM.to_string(), 900000)
         .unwrap();
     let res = app
         .wrap()
@@ -119,13 +113,7 @@ pub fn withdraw() {
     assert_eq!(res, coin(100000, REWARD_DENOM));
 
     // test must be owner
-    let res = contract.withdraw(
-        &mut app,
-        &user,
-        user.to_string(),
-        REWARD_DENOM.to_string(),
-        100000,
-    );
+    let res = contract.withdraw(&mut app, &user, REWARD_DENOM.to_string(), 100000);
 
     assert!(res.is_err());
 }
@@ -1,6 +1,8 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::Addr;
+use cw_utils::Expiration;
 
-use crate::state::{ClaimInfo, State, UserInfo};
+use crate::state::{ClaimInfo, PendingOwner, State, UserInfo};
 
 #[cw_serde]
 pub struct UserInfoItem {
@@ -17,6 +19,14 @@ pub enum ExecuteMsg {
     TogglePause {},
     TransferOwnership {
         address: String,
+        expiry: Option<Expiration>,
+    },
+    AcceptOwnership {},
+    CancelOwnershipTransfer {},
+    UpdateRoles {
+        operator: Option<String>,
+        treasury: Option<String>,
+        pauser: Option<String>,
     },
     UpdateClaimInfo {
         claim_info: ClaimInfo,
@@ -26,7 +36,6 @@ pub enum ExecuteMsg {
         users: Vec<UserInfoItem>,
     },
     Withdraw {
-        address: String,
         denom: String,
         amount: u128,
     },
@@ -52,4 +61,16 @@ pub enum QueryMsg {
 
     #[returns(Vec<UserInfoItem>)]
     GetUsers {},
+
+    #[returns(GetRolesResponse)]
+    GetRoles {},
+}
+
+#[cw_serde]
+pub struct GetRolesResponse {
+    pub owner: Addr,
+    pub pending_owner: Option<PendingOwner>,
+    pub operator: Addr,
+    pub treasury: Addr,
+    pub pauser: Addr,
 }
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::{Addr, StdError, Timestamp};
 use cw_storage_plus::{Item, Map};
+use cw_utils::Expiration;
 
 use crate::ContractError;
 
@@ -12,6 +13,19 @@ pub struct State {
     pub paused: bool,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwner {
+    pub address: Addr,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Roles {
+    pub operator: Addr, // manages users
+    pub treasury: Addr, // receives withdrawals
+    pub pauser: Addr,   // toggles pause
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct ClaimInfo {
     pub reward_denom: String,
@@ -40,6 +54,24 @@ impl State {
     }
 }
 
+impl Roles {
+    pub fn check_operator(&self, sender: Addr) -> Result<(), ContractError> {
+        if sender != self.operator {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        Ok(())
+    }
+
+    pub fn check_pauser(&self, sender: Addr) -> Result<(), ContractError> {
+        if sender != self.pauser {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        Ok(())
+    }
+}
+
 impl ClaimInfo {
     pub fn is_started(&self, time: Timestamp) -> Result<bool, StdError> {
         if time < self.vesting_start {
@@ -83,5 +115,7 @@ pub struct UserInfo {
 }
 
 pub const STATE: Item<State> = Item::new("state");
+pub const PENDING_OWNER: Item<PendingOwner> = Item::new("pending_owner");
+pub const ROLES: Item<Roles> = Item::new("roles");
 pub const CLAIM_INFO: Item<ClaimInfo> = Item::new("claim_info");
 pub const USER_INFO: Map<&Addr, UserInfo> = Map::new("users");
@@ -1,13 +1,15 @@
 use cosmwasm_std::{
     from_json,
     testing::{mock_dependencies, mock_env, mock_info},
-    Timestamp,
+    Addr, Timestamp,
 };
 
+use cw_utils::Expiration;
+
 use crate::{
     contract::{execute, instantiate, query},
-    msg::{ExecuteMsg, InstantiateMsg, QueryMsg, UserInfoItem},
-    state::{ClaimInfo, UserInfo},
+    msg::{ExecuteMsg, GetRolesResponse, InstantiateMsg, QueryMsg, UserInfoItem},
+    state::{ClaimInfo, PendingOwner, UserInfo},
 };
 
 const REWARD_DENOM: &str = "reward_denom";
@@ -150,6 +152,138 @@ fn get_set_users() {
     assert_eq!(user_res.withdrawn, 10);
 }
 
+#[test]
+fn transfer_ownership() {
+    let mut deps = mock_dependencies();
+    let info = mock_info("creator", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), default_msg()).unwrap();
+
+    let msg = ExecuteMsg::TransferOwnership {
+        address: "new_owner".to_string(),
+        expiry: Some(Expiration::AtHeight(mock_env().block.height + 10)),
+    };
+
+    // Test for unauthorized
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user", &[]),
+        msg.clone(),
+    );
+    assert!(_res.is_err());
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    // owner is unchanged until accepted
+    let roles_res = query(deps.as_ref(), mock_env(), QueryMsg::GetRoles {}).unwrap();
+    let roles_res: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(roles_res.owner, "creator".to_string());
+    assert_eq!(
+        roles_res.pending_owner,
+        Some(PendingOwner {
+            address: Addr::unchecked("new_owner"),
+            expires: Expiration::AtHeight(mock_env().block.height + 10),
+        })
+    );
+
+    // only pending owner can accept
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    );
+    assert!(_res.is_err());
+
+    // can't accept after expiry
+    let mut env = mock_env();
+    env.block.height += 10;
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("new_owner", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    );
+    assert!(_res.is_err());
+
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("new_owner", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap();
+
+    let state_res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+    let state_res: crate::state::State = from_json(state_res).unwrap();
+    assert_eq!(state_res.owner, "new_owner".to_string());
+
+    let roles_res = query(deps.as_ref(), mock_env(), QueryMsg::GetRoles {}).unwrap();
+    let roles_res: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(roles_res.pending_owner, None);
+}
+
+#[test]
+fn update_roles() {
+    let mut deps = mock_dependencies();
+    let info = mock_info("creator", &[]);
+    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), default_msg()).unwrap();
+
+    // roles start with the owner
+    let roles_res = query(deps.as_ref(), mock_env(), QueryMsg::GetRoles {}).unwrap();
+    let roles_res: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(roles_res.operator, "creator".to_string());
+    assert_eq!(roles_res.treasury, "creator".to_string());
+    assert_eq!(roles_res.pauser, "creator".to_string());
+
+    let msg = ExecuteMsg::UpdateRoles {
+        operator: Some("operator".to_string()),
+        treasury: None,
+        pauser: Some("pauser".to_string()),
+    };
+
+    // Test for unauthorized
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("operator", &[]),
+        msg.clone(),
+    );
+    assert!(_res.is_err());
+
+    let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let roles_res = query(deps.as_ref(), mock_env(), QueryMsg::GetRoles {}).unwrap();
+    let roles_res: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(roles_res.operator, "operator".to_string());
+    assert_eq!(roles_res.treasury, "creator".to_string());
+    assert_eq!(roles_res.pauser, "pauser".to_string());
+
+    // pausing belongs to the pauser
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::TogglePause {},
+    );
+    assert!(_res.is_err());
+
+    let _res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("pauser", &[]),
+        ExecuteMsg::TogglePause {},
+    )
+    .unwrap();
+
+    // users belong to the operator
+    let msg = ExecuteMsg::SetUsers { users: vec![] };
+    let _res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+    assert!(_res.is_err());
+
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("operator", &[]), msg).unwrap();
+}
+
 #[test]
 fn claim_unlocked_amount() {
     let claim_info = ClaimInfo {
@@ -2,11 +2,21 @@
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
 use cw2::set_contract_version;
-use cw_utils::must_pay;
+use cw_utils::one_coin;
+use injective_cosmwasm::{
+    create_new_denom_msg, create_set_token_metadata_msg, InjectiveMsgWrapper, InjectiveQueryWrapper,
+};
 
 use crate::error::ContractError;
 use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{SaleConfig, SaleData, State, SALE_CONFIG, SALE_DATA, STATE};
+use crate::state::{
+    AntiBotConfig, BeneficiaryConfig, CapUnit, FcfsConfig, InsuranceConfig, ReferralConfig,
+    ReferralRewardDenom, RegistrationConfig, Roles, SaleConfig, SaleData, State, TransferConfig,
+    ALLOCATION_NFT, ANTI_BOT_CONFIG, BENEFICIARY_CONFIG, FEE_RECIPIENTS, INSURANCE_CONFIG, RAISED,
+    REFERRAL_CONFIG, REGISTRATIONS, REGISTRATION_CONFIG, REGISTRATION_DATA, ROLES, SALE_CONFIG,
+    SALE_DATA, STATE, TOKEN_FACTORY_DENOM, TRANSFER_CONFIG,
+};
+use crate::token_factory::factory_denom;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:moon-sale";
@@ -14,264 +24,2788 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
-    _env: Env,
+    deps: DepsMut<InjectiveQueryWrapper>,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     let state = State {
         owner: info.sender.clone(),
+        pause_reason: None,
     };
     STATE.save(deps.storage, &state)?;
 
-    SALE_CONFIG.save(deps.storage, &msg.sale)?;
+    // all roles start with the owner
+    ROLES.save(
+        deps.storage,
+        &Roles {
+            operator: info.sender.clone(),
+            treasury: info.sender.clone(),
+            pauser: info.sender.clone(),
+        },
+    )?;
+
+    msg.sale.validate()?;
+
+    // the contract owns the sale token denom and mints it at finalization
+    let mut sale = msg.sale;
+    let mut messages = vec![];
+    if let Some(token_factory) = msg.token_factory {
+        let denom = factory_denom(&env.contract.address, &token_factory.subdenom);
+        messages.push(create_new_denom_msg(
+            env.contract.address.to_string(),
+            token_factory.subdenom,
+        ));
+        messages.push(create_set_token_metadata_msg(
+            denom.clone(),
+            token_factory.name,
+            token_factory.symbol,
+            token_factory.decimals,
+        ));
+
+        TOKEN_FACTORY_DENOM.save(deps.storage, &denom)?;
+        sale.sale_denom = Some(denom);
+    }
+
+    SALE_CONFIG.save(deps.storage, &sale)?;
+    FEE_RECIPIENTS.save(deps.storage, &vec![])?;
+    REFERRAL_CONFIG.save(
+        deps.storage,
+        &ReferralConfig {
+            reward_bps: 0,
+            reward_denom: ReferralRewardDenom::Pay,
+        },
+    )?;
+    BENEFICIARY_CONFIG.save(
+        deps.storage,
+        &BeneficiaryConfig {
+            approved_only: true,
+            approved_senders: vec![],
+        },
+    )?;
+    ANTI_BOT_CONFIG.save(deps.storage, &AntiBotConfig::default())?;
+    TRANSFER_CONFIG.save(
+        deps.storage,
+        &TransferConfig {
+            enabled: false,
+            whitelisted_only: false,
+        },
+    )?;
 
-    SALE_DATA.save(deps.storage, &SaleData { total_spent: 0 })?;
+    SALE_DATA.save(
+        deps.storage,
+        &SaleData {
+            total_spent: 0,
+            total_tokens_sold: 0,
+            referral_tokens: 0,
+            total_claimed: 0,
+            finalized_at: None,
+            dust: 0,
+            buys: 0,
+            total_allocated: 0,
+            allocation_spent: 0,
+            tokens_returned: 0,
+            sold_out_at: None,
+            contributors: 0,
+        },
+    )?;
 
     Ok(Response::new()
+        .add_messages(messages)
         .add_attribute("method", "instantiate")
         .add_attribute("owner", info.sender))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    deps: DepsMut,
+    deps: DepsMut<InjectiveQueryWrapper>,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
     match msg {
-        ExecuteMsg::UpdateSale { sale } => execute::update_sale(deps, info, sale),
-        ExecuteMsg::SetUserList { users } => execute::set_user_list(deps, info, users),
-        ExecuteMsg::Buy {} => execute::buy(deps, env, info),
-        ExecuteMsg::Withdraw { to, funds } => execute::withdraw(deps, info, to, funds),
-        ExecuteMsg::TransferOwnership { address } => {
-            execute::transfer_ownership(deps, info, address)
+        ExecuteMsg::UpdateSale { sale } => execute::update_sale(deps, env, info, sale),
+        ExecuteMsg::SetUserList { users } => execute::set_user_list(deps, env, info, users),
+        ExecuteMsg::SetPriceOverride { address, price } => {
+            execute::set_price_override(deps, env, info, address, price)
+        }
+        ExecuteMsg::RemoveUsers { addresses } => execute::remove_users(deps, info, addresses),
+        ExecuteMsg::AdjustAllocations { address, delta } => {
+            execute::adjust_allocations(deps, info, address, delta)
+        }
+        ExecuteMsg::TransferAllocation { to, amount } => {
+            execute::transfer_allocation(deps, info, to, amount)
+        }
+        ExecuteMsg::UpdateTransferConfig { config } => {
+            execute::update_transfer_config(deps, info, config)
+        }
+        ExecuteMsg::UpdateAllocationNft { contract } => {
+            execute::update_allocation_nft(deps, info, contract)
+        }
+        ExecuteMsg::SetNftAllocations { allocations } => {
+            execute::set_nft_allocations(deps, info, allocations)
+        }
+        ExecuteMsg::Buy {
+            referrer,
+            partial_fill,
+            min_tokens_out,
+            beneficiary,
+            token_id,
+        } => execute::buy(
+            deps,
+            env,
+            info,
+            execute::BuyOptions {
+                referrer,
+                partial_fill: partial_fill.unwrap_or(false),
+                min_tokens_out,
+                beneficiary,
+                token_id,
+            },
+        ),
+        ExecuteMsg::Withdraw { funds } => execute::withdraw(deps, env, info, funds),
+        ExecuteMsg::TransferOwnership { address, expiry } => {
+            execute::transfer_ownership(deps, env, info, address, expiry)
+        }
+        ExecuteMsg::AcceptOwnership {} => execute::accept_ownership(deps, env, info),
+        ExecuteMsg::CancelOwnershipTransfer {} => execute::cancel_ownership_transfer(deps, info),
+        ExecuteMsg::UpdateRoles {
+            operator,
+            treasury,
+            pauser,
+        } => execute::update_roles(deps, info, operator, treasury, pauser),
+        ExecuteMsg::Pause { reason } => execute::pause(deps, info, reason),
+        ExecuteMsg::Unpause {} => execute::unpause(deps, info),
+        ExecuteMsg::UpdateFeeRecipients { recipients } => {
+            execute::update_fee_recipients(deps, info, recipients)
+        }
+        ExecuteMsg::Settle {} => execute::settle(deps, env),
+        ExecuteMsg::UpdateReferralConfig { config } => {
+            execute::update_referral_config(deps, info, config)
         }
+        ExecuteMsg::ClaimReferralRewards {} => execute::claim_referral_rewards(deps, env, info),
+        ExecuteMsg::UpdateBeneficiaryConfig {
+            approved_only,
+            approved_senders,
+        } => execute::update_beneficiary_config(deps, info, approved_only, approved_senders),
+        ExecuteMsg::UpdateAntiBotConfig { config } => {
+            execute::update_anti_bot_config(deps, info, config)
+        }
+        ExecuteMsg::UpdateFcfsConfig { config } => execute::update_fcfs_config(deps, info, config),
+        ExecuteMsg::Finalize {} => execute::finalize(deps, env, info),
+        ExecuteMsg::Claim {} => execute::claim(deps, info),
+        ExecuteMsg::CancelSale { reason } => execute::cancel_sale(deps, env, info, reason),
+        ExecuteMsg::Refund {} => execute::refund(deps, info),
+        ExecuteMsg::PushRefunds { limit } => execute::push_refunds(deps, info, limit),
+        ExecuteMsg::UpdateInsuranceConfig { config } => {
+            execute::update_insurance_config(deps, info, config)
+        }
+        ExecuteMsg::ClaimInsurance {} => execute::claim_insurance(deps, env, info),
+        ExecuteMsg::UpdateRegistrationConfig { config } => {
+            execute::update_registration_config(deps, info, config)
+        }
+        ExecuteMsg::Register { weight } => execute::register(deps, env, info, weight),
+        ExecuteMsg::Unregister {} => execute::unregister(deps, env, info),
+        ExecuteMsg::AllocateRegistrations { limit } => {
+            execute::allocate_registrations(deps, env, limit)
+        }
+        ExecuteMsg::ReturnDeposit {} => execute::return_deposit(deps, env, info),
     }
 }
 
-pub mod execute {
-    use cosmwasm_std::{BankMsg, Coin};
+pub mod execute {
+    use cosmwasm_std::{
+        coin, coins, Addr, BankMsg, Coin, Decimal, Decimal256, Event, Order, Storage, Uint128,
+    };
+    use cw_storage_plus::Bound;
+    use cw_utils::Expiration;
+
+    use crate::{
+        msg::{FeeRecipientReq, NftAllocationReq, UserInfoReq},
+        oracle,
+        state::{
+            AllocationRule, BlockVolume, BuyerActivity, Cancellation, Contribution, FeeRecipient,
+            PendingOwner, PriceOverride, Registration, UserInfo, ALLOCATION_NFT, BLOCK_VOLUME,
+            BUYER_ACTIVITY, CANCELLATION, CLAIMED, CONTRIBUTIONS, FCFS_CONFIG, FEES,
+            NFT_ALLOCATIONS, PENDING_OWNER, REFERRALS, REFERRAL_REWARDS, USER_LIST,
+        },
+        token_factory::change_admin_msg,
+    };
+    use injective_cosmwasm::create_mint_tokens_msg;
+
+    use super::*;
+
+    const DEFAULT_REFUND_LIMIT: u32 = 10;
+    const MAX_REFUND_LIMIT: u32 = 30;
+    const DEFAULT_ALLOCATE_LIMIT: u32 = 30;
+    const MAX_ALLOCATE_LIMIT: u32 = 100;
+
+    fn check_not_cancelled(storage: &dyn Storage) -> Result<(), ContractError> {
+        if let Some(cancellation) = CANCELLATION.may_load(storage)? {
+            return Err(ContractError::SaleCancelled {
+                reason: cancellation.reason,
+            });
+        }
+
+        Ok(())
+    }
+
+    // allocations are promises against the supply unless the sale is oversubscribed
+    fn check_allocated(sale: &SaleConfig, sale_data: &SaleData) -> Result<(), ContractError> {
+        if !sale.allow_oversubscription && sale_data.total_allocated > sale.max_supply {
+            return Err(ContractError::AllocationExceedsSupply {
+                allocated: sale_data.total_allocated,
+                max: sale.max_supply,
+            });
+        }
+
+        Ok(())
+    }
+
+    // raised funds stay put while the sale can still be cancelled
+    fn check_finalized(storage: &dyn Storage) -> Result<(), ContractError> {
+        if SALE_DATA.load(storage)?.finalized_at.is_none() {
+            return Err(ContractError::NotFinalized {});
+        }
+
+        Ok(())
+    }
+
+    pub fn transfer_ownership(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        address: String,
+        expiry: Option<Expiration>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let expires = expiry.unwrap_or_default();
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+
+        // new owner has to accept before the transfer takes effect
+        let new_owner = deps.api.addr_validate(&address)?;
+        PENDING_OWNER.save(
+            deps.storage,
+            &PendingOwner {
+                address: new_owner.clone(),
+                expires,
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "transfer_ownership")
+            .add_attribute("from", info.sender)
+            .add_attribute("to", new_owner)
+            .add_attribute("expires", expires.to_string()))
+    }
+
+    pub fn accept_ownership(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let pending = PENDING_OWNER
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoPendingOwner {})?;
+
+        // only pending owner
+        if info.sender != pending.address {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        if pending.expires.is_expired(&env.block) {
+            return Err(ContractError::OwnershipTransferExpired {});
+        }
+
+        let previous_owner = STATE.load(deps.storage)?.owner;
+        STATE.update(deps.storage, |mut state| -> StdResult<_> {
+            state.owner = pending.address.clone();
+            Ok(state)
+        })?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("action", "accept_ownership")
+            .add_attribute("from", previous_owner)
+            .add_attribute("to", pending.address))
+    }
+
+    pub fn cancel_ownership_transfer(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        if !PENDING_OWNER.exists(deps.storage) {
+            return Err(ContractError::NoPendingOwner {});
+        }
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new().add_attribute("action", "cancel_ownership_transfer"))
+    }
+
+    pub fn update_roles(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        operator: Option<String>,
+        treasury: Option<String>,
+        pauser: Option<String>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let mut roles = ROLES.load(deps.storage)?;
+        if let Some(operator) = operator {
+            roles.operator = deps.api.addr_validate(&operator)?;
+        }
+        if let Some(treasury) = treasury {
+            roles.treasury = deps.api.addr_validate(&treasury)?;
+        }
+        if let Some(pauser) = pauser {
+            roles.pauser = deps.api.addr_validate(&pauser)?;
+        }
+        ROLES.save(deps.storage, &roles)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_roles")
+            .add_attribute("operator", roles.operator)
+            .add_attribute("treasury", roles.treasury)
+            .add_attribute("pauser", roles.pauser))
+    }
+
+    fn check_insurance_closed(storage: &dyn Storage, env: &Env) -> Result<(), ContractError> {
+        if let Some(config) = INSURANCE_CONFIG.may_load(storage)? {
+            let finalized_at = SALE_DATA.load(storage)?.finalized_at;
+            if !config.is_closed(finalized_at, env.block.time) {
+                return Err(ContractError::InsuranceWindowOpen {});
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn pause(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        reason: String,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only pauser
+        ROLES.load(deps.storage)?.check_pauser(&info.sender)?;
+
+        STATE.update(deps.storage, |mut state| -> StdResult<_> {
+            state.pause_reason = Some(reason.clone());
+            Ok(state)
+        })?;
+
+        Ok(Response::new()
+            .add_attribute("action", "pause")
+            .add_attribute("reason", reason))
+    }
+
+    pub fn unpause(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only pauser
+        ROLES.load(deps.storage)?.check_pauser(&info.sender)?;
+
+        let mut state = STATE.load(deps.storage)?;
+        if state.pause_reason.is_none() {
+            return Err(ContractError::NotPaused {});
+        }
+        state.pause_reason = None;
+        STATE.save(deps.storage, &state)?;
+
+        Ok(Response::new().add_attribute("action", "unpause"))
+    }
+
+    pub fn update_fee_recipients(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        recipients: Vec<FeeRecipientReq>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let recipients = recipients
+            .into_iter()
+            .map(|recipient| {
+                Ok(FeeRecipient {
+                    address: deps.api.addr_validate(&recipient.address)?,
+                    label: recipient.label,
+                    bps: recipient.bps,
+                })
+            })
+            .collect::<StdResult<Vec<FeeRecipient>>>()?;
+
+        // fees and pay denom referral rewards come out of the same funds
+        let total: u64 = recipients.iter().map(|recipient| recipient.bps).sum();
+        if total + pay_referral_bps(deps.storage)? > 10000 {
+            return Err(ContractError::InvalidFeeBps { total });
+        }
+
+        // already accrued fees stay with their recipients
+        FEE_RECIPIENTS.save(deps.storage, &recipients)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_fee_recipients")
+            .add_attribute("total_bps", total.to_string()))
+    }
+
+    // Pays out all pending fees to their recipients
+    pub fn settle(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // fees of a cancelled sale are refunded to buyers, insured ones wait for the window
+        check_not_cancelled(deps.storage)?;
+        check_finalized(deps.storage)?;
+        check_insurance_closed(deps.storage, &env)?;
+
+        let pending = FEES
+            .range(deps.storage, None, None, Order::Ascending)
+            .filter(|item| item.as_ref().map_or(true, |(_, fee)| fee.pending > 0))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        let mut messages = vec![];
+        for ((address, denom), mut fee) in pending {
+            messages.push(BankMsg::Send {
+                to_address: address.to_string(),
+                amount: coins(fee.pending, &denom),
+            });
+
+            RAISED.update(deps.storage, &denom, |raised| {
+                let mut raised = raised.unwrap_or_default();
+                raised.pending_fees -= fee.pending;
+                StdResult::Ok(raised)
+            })?;
+            fee.settled += fee.pending;
+            fee.pending = 0;
+            FEES.save(deps.storage, (&address, &denom), &fee)?;
+        }
+
+        Ok(Response::new()
+            .add_messages(messages)
+            .add_attribute("action", "settle"))
+    }
+
+    // Splits `amount` between the fee recipients, returns the total fee
+    fn accrue_fees(storage: &mut dyn Storage, denom: &str, amount: u128) -> StdResult<u128> {
+        let mut total = 0;
+        for recipient in FEE_RECIPIENTS.load(storage)? {
+            let fee = recipient.fee(amount);
+            if fee == 0 {
+                continue;
+            }
+
+            FEES.update(storage, (&recipient.address, denom), |balance| {
+                let mut balance = balance.unwrap_or_default();
+                balance.pending += fee;
+                StdResult::Ok(balance)
+            })?;
+            total += fee;
+        }
+
+        Ok(total)
+    }
+
+    pub fn update_referral_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: ReferralConfig,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let fee_bps: u64 = FEE_RECIPIENTS
+            .load(deps.storage)?
+            .iter()
+            .map(|recipient| recipient.bps)
+            .sum();
+        let max_bps = match config.reward_denom {
+            ReferralRewardDenom::Pay => 10000 - fee_bps,
+            ReferralRewardDenom::Sale => 10000,
+        };
+        if config.reward_bps > max_bps {
+            return Err(ContractError::InvalidReferralBps {
+                bps: config.reward_bps,
+            });
+        }
+
+        REFERRAL_CONFIG.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_referral_config")
+            .add_attribute("reward_bps", config.reward_bps.to_string()))
+    }
+
+    pub fn update_beneficiary_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        approved_only: bool,
+        approved_senders: Vec<String>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let approved_senders = approved_senders
+            .iter()
+            .map(|sender| deps.api.addr_validate(sender))
+            .collect::<StdResult<Vec<_>>>()?;
+        BENEFICIARY_CONFIG.save(
+            deps.storage,
+            &BeneficiaryConfig {
+                approved_only,
+                approved_senders,
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_beneficiary_config")
+            .add_attribute("approved_only", approved_only.to_string()))
+    }
+
+    pub fn update_anti_bot_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: AntiBotConfig,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        ANTI_BOT_CONFIG.save(deps.storage, &config)?;
+
+        Ok(Response::new().add_attribute("action", "update_anti_bot_config"))
+    }
+
+    pub fn update_fcfs_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: Option<FcfsConfig>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        match config {
+            Some(config) => {
+                if config.multiplier < Decimal::one() {
+                    return Err(ContractError::InvalidFcfsMultiplier {});
+                }
+                FCFS_CONFIG.save(deps.storage, &config)?;
+            }
+            None => FCFS_CONFIG.remove(deps.storage),
+        }
+
+        Ok(Response::new().add_attribute("action", "update_fcfs_config"))
+    }
+
+    pub fn update_insurance_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: Option<InsuranceConfig>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        // terms are fixed at finalize
+        if SALE_DATA.load(deps.storage)?.finalized_at.is_some() {
+            return Err(ContractError::AlreadyFinalized {});
+        }
+
+        match config {
+            Some(config) => {
+                config.validate(&SALE_CONFIG.load(deps.storage)?)?;
+                INSURANCE_CONFIG.save(deps.storage, &config)?;
+            }
+            None => INSURANCE_CONFIG.remove(deps.storage),
+        }
+
+        Ok(Response::new().add_attribute("action", "update_insurance_config"))
+    }
+
+    pub fn claim_referral_rewards(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // rewards of a cancelled sale are refunded to buyers, insured ones wait for the window
+        check_not_cancelled(deps.storage)?;
+        check_finalized(deps.storage)?;
+        check_insurance_closed(deps.storage, &env)?;
+
+        let pending = REFERRAL_REWARDS
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .filter(|item| item.as_ref().map_or(true, |(_, reward)| reward.pending > 0))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        if pending.is_empty() {
+            return Err(ContractError::NothingToClaim {});
+        }
+
+        let mut amount = vec![];
+        for (denom, mut reward) in pending {
+            amount.push(Coin::new(reward.pending, &denom));
+            RAISED.update(deps.storage, &denom, |raised| {
+                let mut raised = raised.unwrap_or_default();
+                raised.pending_rewards -= reward.pending;
+                StdResult::Ok(raised)
+            })?;
+
+            reward.settled += reward.pending;
+            reward.pending = 0;
+            REFERRAL_REWARDS.save(deps.storage, (&info.sender, &denom), &reward)?;
+        }
+
+        Ok(Response::new()
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount,
+            })
+            .add_attribute("action", "claim_referral_rewards")
+            .add_attribute("address", info.sender))
+    }
+
+    fn pay_referral_bps(storage: &dyn Storage) -> StdResult<u64> {
+        let config = REFERRAL_CONFIG.load(storage)?;
+        Ok(match config.reward_denom {
+            ReferralRewardDenom::Pay => config.reward_bps,
+            ReferralRewardDenom::Sale => 0,
+        })
+    }
+
+    // Records referred volume (in pay denom units) and the referrer's reward, returns the reward
+    fn record_referral(
+        storage: &mut dyn Storage,
+        sale_data: &mut SaleData,
+        referrer: &Addr,
+        denom: &str,
+        amount: u128,
+        volume: u128,
+        tokens: u128,
+    ) -> StdResult<u128> {
+        let config = REFERRAL_CONFIG.load(storage)?;
+        let mut info = REFERRALS.may_load(storage, referrer)?.unwrap_or_default();
+        info.volume += volume;
+        info.buys += 1;
+
+        let reward = match config.reward_denom {
+            ReferralRewardDenom::Pay => {
+                let reward = config.reward(amount);
+                if reward > 0 {
+                    REFERRAL_REWARDS.update(storage, (referrer, denom), |balance| {
+                        let mut balance = balance.unwrap_or_default();
+                        balance.pending += reward;
+                        StdResult::Ok(balance)
+                    })?;
+                }
+                reward
+            }
+            ReferralRewardDenom::Sale => {
+                let reward = config.reward(tokens);
+                info.token_reward += reward;
+                sale_data.referral_tokens += reward;
+                reward
+            }
+        };
+        REFERRALS.save(storage, referrer, &info)?;
+
+        Ok(reward)
+    }
+
+    pub fn update_sale(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        sale: SaleConfig,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        sale.validate()?;
+        if let Some(insurance) = INSURANCE_CONFIG.may_load(deps.storage)? {
+            insurance.validate(&sale)?;
+        }
+        let current = SALE_CONFIG.load(deps.storage)?;
+        let sale_data = SALE_DATA.load(deps.storage)?;
+
+        // tokens were minted for what was sold, the sale can't reopen
+        if sale_data.finalized_at.is_some() {
+            return Err(ContractError::AlreadyFinalized {});
+        }
+
+        // price and denom are fixed once buying has begun, even if the start is moved back
+        if current.is_started(&env.block) || sale_data.total_spent > 0 {
+            if sale.price_pay_amount != current.price_pay_amount
+                || sale.price_receive_amount != current.price_receive_amount
+                || sale.extra_pay_denoms != current.extra_pay_denoms
+                || sale.pay_decimals != current.pay_decimals
+                || sale.sale_decimals != current.sale_decimals
+            {
+                return Err(ContractError::PriceLocked {});
+            }
+            if sale.pay_denom != current.pay_denom {
+                return Err(ContractError::PayDenomLocked {});
+            }
+            if sale.cap_unit != current.cap_unit {
+                return Err(ContractError::CapUnitLocked {});
+            }
+        }
+
+        // token factory denom belongs to this contract
+        if let Some(denom) = TOKEN_FACTORY_DENOM.may_load(deps.storage)? {
+            if sale.sale_denom.as_ref() != Some(&denom) {
+                return Err(ContractError::SaleDenomLocked {});
+            }
+        }
+
+        // can't sell less than what was already bought
+        if sale.max_supply < sale_data.total_spent {
+            return Err(ContractError::MaxSupplyBelowSpent {
+                max_supply: sale.max_supply,
+                total_spent: sale_data.total_spent,
+            });
+        }
+
+        check_allocated(&sale, &sale_data)?;
+
+        SALE_CONFIG.save(deps.storage, &sale)?;
+        Ok(Response::new().add_attribute("action", "update_sale"))
+    }
+
+    // price overrides are the owner's call and lock like the sale price
+    fn check_price_override(
+        address: &Addr,
+        price: &Option<PriceOverride>,
+        started: bool,
+        spent: u128,
+    ) -> Result<(), ContractError> {
+        if let Some(price) = price {
+            if price.price_pay_amount == 0 || price.price_receive_amount == 0 {
+                return Err(ContractError::InvalidPriceOverride {
+                    address: address.to_string(),
+                });
+            }
+        }
+        if started || spent > 0 {
+            return Err(ContractError::PriceLocked {});
+        }
+
+        Ok(())
+    }
+
+    pub fn set_user_list(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        users: Vec<UserInfoReq>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only operator
+        ROLES.load(deps.storage)?.check_operator(&info.sender)?;
+
+        let state = STATE.load(deps.storage)?;
+        let started = SALE_CONFIG.load(deps.storage)?.is_started(&env.block);
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        for user in users {
+            let addr = deps.api.addr_validate(&user.address)?;
+
+            // keep buy history of existing users, `spent` is only used for new entries
+            let existing = USER_LIST.may_load(deps.storage, &addr)?;
+            let (spent, received_amount, bought) = match &existing {
+                Some(existing) => {
+                    sale_data.total_allocated -= existing.allocation;
+                    (existing.spent, existing.received_amount, existing.bought)
+                }
+                None => {
+                    sale_data.allocation_spent += user.spent;
+                    (user.spent, 0, 0)
+                }
+            };
+            if user.price != existing.and_then(|existing| existing.price) {
+                state.check_owner(&info.sender)?;
+                check_price_override(&addr, &user.price, started, spent)?;
+            }
+            sale_data.total_allocated += user.allocation;
+
+            if user.allocation < spent {
+                return Err(ContractError::AllocationBelowSpent {
+                    address: addr.to_string(),
+                    allocation: user.allocation,
+                    spent,
+                });
+            }
+            USER_LIST.save(
+                deps.storage,
+                &addr,
+                &UserInfo {
+                    allocation: user.allocation,
+                    spent,
+                    received_amount,
+                    price: user.price,
+                    bought,
+                },
+            )?;
+        }
+        check_allocated(&SALE_CONFIG.load(deps.storage)?, &sale_data)?;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+        Ok(Response::new().add_attribute("action", "set_user_list"))
+    }
+
+    pub fn set_price_override(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        address: String,
+        price: Option<PriceOverride>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let addr = deps.api.addr_validate(&address)?;
+        let mut user = USER_LIST.load(deps.storage, &addr)?;
+        let started = SALE_CONFIG.load(deps.storage)?.is_started(&env.block);
+        check_price_override(&addr, &price, started, user.spent)?;
+
+        user.price = price;
+        USER_LIST.save(deps.storage, &addr, &user)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "set_price_override")
+            .add_attribute("address", address))
+    }
+
+    pub fn remove_users(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        addresses: Vec<String>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only operator
+        ROLES.load(deps.storage)?.check_operator(&info.sender)?;
+
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        for address in addresses {
+            let addr = deps.api.addr_validate(&address)?;
+            let user = USER_LIST.load(deps.storage, &addr)?;
+
+            // only users without buy history
+            if user.spent > 0 || user.received_amount > 0 {
+                return Err(ContractError::UserAlreadyBought { address });
+            }
+
+            sale_data.total_allocated -= user.allocation;
+            USER_LIST.remove(deps.storage, &addr)?;
+        }
+        SALE_DATA.save(deps.storage, &sale_data)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "remove_users")
+            .add_attribute("total_allocated", sale_data.total_allocated.to_string()))
+    }
+
+    pub fn transfer_allocation(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        to: String,
+        amount: u128,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only if not paused and transfers are enabled
+        STATE.load(deps.storage)?.check_paused()?;
+        let config = TRANSFER_CONFIG.load(deps.storage)?;
+        if !config.enabled {
+            return Err(ContractError::TransfersDisabled {});
+        }
+
+        let to_addr = deps.api.addr_validate(&to)?;
+        if amount == 0 || to_addr == info.sender {
+            return Err(ContractError::InvalidTransfer {});
+        }
+
+        // only unspent allocation
+        let mut from_user = USER_LIST
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(ContractError::NotParticipating {})?;
+        if amount > from_user.available_allocation() {
+            return Err(ContractError::UserAllocationExceeded {
+                wanted: amount,
+                max: from_user.available_allocation(),
+            });
+        }
+
+        // discounted allocation stays with the address it was negotiated for
+        if from_user.price.is_some() {
+            return Err(ContractError::PriceOverrideTransfer {
+                address: info.sender.to_string(),
+            });
+        }
+
+        // to anyone, or only to addresses that already have an allocation
+        let mut to_user = match USER_LIST.may_load(deps.storage, &to_addr)? {
+            Some(user) if user.allocation > 0 || !config.whitelisted_only => user,
+            None if !config.whitelisted_only => UserInfo::default(),
+            _ => return Err(ContractError::RecipientNotWhitelisted { address: to }),
+        };
+        if to_user.price.is_some() {
+            return Err(ContractError::PriceOverrideTransfer { address: to });
+        }
+
+        from_user.allocation -= amount;
+        to_user.allocation += amount;
+        USER_LIST.save(deps.storage, &info.sender, &from_user)?;
+        USER_LIST.save(deps.storage, &to_addr, &to_user)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "transfer_allocation")
+            .add_event(
+                Event::new("transfer_allocation")
+                    .add_attribute("from", info.sender)
+                    .add_attribute("to", to)
+                    .add_attribute("amount", amount.to_string()),
+            ))
+    }
+
+    pub fn update_transfer_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: TransferConfig,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        TRANSFER_CONFIG.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_transfer_config")
+            .add_attribute("enabled", config.enabled.to_string())
+            .add_attribute("whitelisted_only", config.whitelisted_only.to_string()))
+    }
+
+    pub fn adjust_allocations(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        address: String,
+        delta: i128,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only operator
+        ROLES.load(deps.storage)?.check_operator(&info.sender)?;
+
+        let addr = deps.api.addr_validate(&address)?;
+        let mut user = USER_LIST.may_load(deps.storage, &addr)?.unwrap_or_default();
+
+        let allocation =
+            user.allocation
+                .checked_add_signed(delta)
+                .ok_or(ContractError::NegativeAllocation {
+                    address: address.clone(),
+                })?;
+        if allocation < user.spent {
+            return Err(ContractError::AllocationBelowSpent {
+                address,
+                allocation,
+                spent: user.spent,
+            });
+        }
+
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        sale_data.total_allocated = sale_data.total_allocated - user.allocation + allocation;
+        check_allocated(&SALE_CONFIG.load(deps.storage)?, &sale_data)?;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+        user.allocation = allocation;
+        USER_LIST.save(deps.storage, &addr, &user)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "adjust_allocations")
+            .add_attribute("address", address)
+            .add_attribute("allocation", allocation.to_string())
+            .add_attribute("total_allocated", sale_data.total_allocated.to_string()))
+    }
+
+    pub fn update_allocation_nft(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        contract: Option<String>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        match &contract {
+            Some(contract) => {
+                ALLOCATION_NFT.save(deps.storage, &deps.api.addr_validate(contract)?)?
+            }
+            None => ALLOCATION_NFT.remove(deps.storage),
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "update_allocation_nft")
+            .add_attribute("contract", contract.unwrap_or_else(|| "none".to_string())))
+    }
+
+    pub fn set_nft_allocations(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        allocations: Vec<NftAllocationReq>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only operator
+        ROLES.load(deps.storage)?.check_operator(&info.sender)?;
+
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        for req in allocations {
+            // keep what was already bought with the token
+            let mut allocation = NFT_ALLOCATIONS
+                .may_load(deps.storage, &req.token_id)?
+                .unwrap_or_default();
+            if req.allocation < allocation.spent {
+                return Err(ContractError::AllocationBelowSpent {
+                    address: req.token_id,
+                    allocation: req.allocation,
+                    spent: allocation.spent,
+                });
+            }
+
+            sale_data.total_allocated =
+                sale_data.total_allocated - allocation.allocation + req.allocation;
+            allocation.allocation = req.allocation;
+            NFT_ALLOCATIONS.save(deps.storage, &req.token_id, &allocation)?;
+        }
+        check_allocated(&SALE_CONFIG.load(deps.storage)?, &sale_data)?;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+
+        Ok(Response::new().add_attribute("action", "set_nft_allocations"))
+    }
+
+    // optional parts of a buy
+    pub struct BuyOptions {
+        pub referrer: Option<String>,
+        pub partial_fill: bool,
+        pub min_tokens_out: Option<u128>,
+        pub beneficiary: Option<String>,
+        pub token_id: Option<String>,
+    }
+
+    // A buy that passed every check, ready to be applied
+    pub struct BuyPlan {
+        pub paid: Coin,
+        pub paid_amount: u128, // filled part of `paid`
+        pub refund: u128,
+        pub buyer: Addr,
+        pub user: UserInfo, // the buyer's entry or the NFT's allocation
+        pub token_id: Option<String>,
+        pub quote: query::Quote,
+        pub used: u128, // counted against allocations and supply
+        pub referrer: Option<Addr>,
+        pub activity: BuyerActivity,
+        pub next_buy: Option<Expiration>,
+        pub block_bought: u128,
+        pub sale: SaleConfig,
+        pub sale_data: SaleData,
+    }
+
+    // Runs every buy check without touching state
+    pub fn plan_buy(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        info: &MessageInfo,
+        options: BuyOptions,
+    ) -> Result<BuyPlan, ContractError> {
+        let BuyOptions {
+            referrer,
+            partial_fill,
+            min_tokens_out,
+            beneficiary,
+            token_id,
+        } = options;
+
+        // only if not paused or cancelled
+        STATE.load(deps.storage)?.check_paused()?;
+        check_not_cancelled(deps.storage)?;
+
+        // only if sale is active
+        let sale = SALE_CONFIG.load(deps.storage)?;
+        if !sale.is_active(&env.block) {
+            return Err(ContractError::SaleNotActive {});
+        }
+
+        // never once the tokens were minted
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        if sale_data.finalized_at.is_some() {
+            return Err(ContractError::AlreadyFinalized {});
+        }
+
+        // only a single accepted denom
+        let paid = one_coin(info).map_err(|_| ContractError::MissingFunds {})?;
+        let price = sale
+            .price(&paid.denom)
+            .ok_or(ContractError::UnsupportedDenom {
+                denom: paid.denom.clone(),
+            })?;
+        let mut paid_amount = paid.amount.u128();
+
+        // the sender pays, the beneficiary's allocation is used and credited
+        let buyer = match beneficiary {
+            Some(beneficiary) => deps.api.addr_validate(&beneficiary)?,
+            None => info.sender.clone(),
+        };
+        if buyer != info.sender && !BENEFICIARY_CONFIG.load(deps.storage)?.allows(&info.sender) {
+            return Err(ContractError::BeneficiaryNotAllowed {});
+        }
+
+        // the allocation comes from the buyer's entry, or from an allocation NFT they hold
+        let user = match &token_id {
+            Some(token_id) => {
+                query::check_nft_owner(deps, token_id, &buyer)?;
+                NFT_ALLOCATIONS.may_load(deps.storage, token_id)?.ok_or(
+                    ContractError::UnknownAllocationNft {
+                        token_id: token_id.clone(),
+                    },
+                )?
+            }
+            None => USER_LIST.load(deps.storage, &buyer)?,
+        };
+
+        // only if user is in list and has available allocation
+        if user.allocation == 0 {
+            return Err(ContractError::NotParticipating {});
+        }
+
+        // allocations and supply are counted in the cap unit, at the user's price if set
+        let user_price = user.price.as_ref();
+        let mut quote = query::quote(deps, env, &sale, &price, paid_amount, user_price)?;
+
+        // only as often as the anti-bot limits allow
+        let anti_bot = ANTI_BOT_CONFIG.load(deps.storage)?;
+        let activity = BUYER_ACTIVITY
+            .may_load(deps.storage, &buyer)?
+            .unwrap_or_default();
+        if let Some(max) = anti_bot.max_buys {
+            if activity.buys >= max {
+                return Err(ContractError::MaxBuysReached { max });
+            }
+        }
+        if let Some(next_buy) = activity.next_buy {
+            if !next_buy.is_expired(&env.block) {
+                return Err(ContractError::CooldownActive { next_buy });
+            }
+        }
+        let block_bought = BLOCK_VOLUME
+            .may_load(deps.storage)?
+            .unwrap_or_default()
+            .at(&env.block);
+        let block_available = anti_bot
+            .max_per_block
+            .map(|max| max.saturating_sub(block_bought));
+
+        let allocation_limit = query::allocation_limit(deps, env, &sale, &sale_data, &user)?;
+
+        // partial fills take what still fits and refund the rest
+        let mut refund = 0;
+        if partial_fill {
+            // caps are in the cap unit, anti-bot limits in pay denom units
+            let cap_limit = allocation_limit.min(sale.max_supply - sale_data.total_spent);
+            let pay_limit = anti_bot
+                .max_per_tx
+                .unwrap_or(u128::MAX)
+                .min(block_available.unwrap_or(u128::MAX));
+            let used = quote.used(&sale.cap_unit);
+            let mut filled = paid.amount;
+            if used > cap_limit {
+                filled = filled.min(paid.amount.multiply_ratio(cap_limit, used));
+            }
+            if quote.value > pay_limit {
+                filled = filled.min(paid.amount.multiply_ratio(pay_limit, quote.value));
+            }
+            if filled < paid.amount && !filled.is_zero() {
+                refund = paid_amount - filled.u128();
+                paid_amount = filled.u128();
+                quote = query::quote(deps, env, &sale, &price, paid_amount, user_price)?;
+            }
+
+            // coarse denoms can round back over a token cap, the buyer gets what fits
+            if sale.cap_unit == CapUnit::Tokens && quote.tokens > cap_limit {
+                let excess = Uint128::from(paid_amount)
+                    .multiply_ratio(quote.tokens - cap_limit, quote.tokens)
+                    .u128();
+                if excess > 0 {
+                    refund += excess;
+                    paid_amount -= excess;
+                    quote = query::quote(deps, env, &sale, &price, paid_amount, user_price)?;
+                }
+                quote.tokens = quote.tokens.min(cap_limit);
+            }
+        }
+        let funds = quote.value;
+        let used = quote.used(&sale.cap_unit);
+        let total_tokens = quote.tokens;
+
+        // only if the buyer gets at least what they asked for
+        if let Some(min_tokens_out) = min_tokens_out {
+            if total_tokens < min_tokens_out {
+                return Err(ContractError::TokensOutBelowMinimum {
+                    tokens_out: total_tokens,
+                    min_tokens_out,
+                });
+            }
+        }
+
+        // only if user has enough allocation left
+        if used > allocation_limit {
+            return Err(ContractError::UserAllocationExceeded {
+                wanted: used,
+                max: allocation_limit,
+            });
+        }
+
+        // only up to the per transaction and per block caps
+        if let Some(max) = anti_bot.max_per_tx {
+            if funds > max {
+                return Err(ContractError::MaxPerTxExceeded { wanted: funds, max });
+            }
+        }
+        if let Some(available) = block_available {
+            if funds > available {
+                return Err(ContractError::BlockCapExceeded {
+                    wanted: funds,
+                    available,
+                });
+            }
+        }
+
+        // only whitelisted referrers, and never the buyer
+        let referrer = referrer
+            .map(|referrer| deps.api.addr_validate(&referrer))
+            .transpose()?;
+        if let Some(referrer) = &referrer {
+            if *referrer == buyer || *referrer == info.sender {
+                return Err(ContractError::SelfReferral {});
+            }
+
+            let whitelisted = USER_LIST
+                .may_load(deps.storage, referrer)?
+                .is_some_and(|user| user.allocation > 0);
+            if !whitelisted {
+                return Err(ContractError::InvalidReferrer {});
+            }
+        }
+
+        let potential_amount: u128 = sale_data.total_spent + used;
+
+        // only if sale has enough allocation left
+        if potential_amount > sale.max_supply {
+            return Err(ContractError::SaleAllocationExceeded {
+                wanted: used,
+                max: sale.max_supply - sale_data.total_spent,
+            });
+        }
+
+        Ok(BuyPlan {
+            paid,
+            paid_amount,
+            refund,
+            buyer,
+            user,
+            token_id,
+            quote,
+            used,
+            referrer,
+            activity,
+            next_buy: anti_bot.cooldown.map(|cooldown| cooldown.after(&env.block)),
+            block_bought,
+            sale,
+            sale_data,
+        })
+    }
+
+    pub fn buy(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        options: BuyOptions,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let BuyPlan {
+            paid,
+            paid_amount,
+            refund,
+            buyer,
+            user,
+            token_id,
+            quote,
+            used,
+            referrer,
+            mut activity,
+            next_buy,
+            block_bought,
+            mut sale,
+            mut sale_data,
+        } = plan_buy(deps.as_ref(), &env, &info, options)?;
+        let funds = quote.value;
+        let total_tokens = quote.tokens;
+
+        let fees = accrue_fees(deps.storage, &paid.denom, paid_amount)?;
+        let referral_reward = match &referrer {
+            Some(referrer) => record_referral(
+                deps.storage,
+                &mut sale_data,
+                referrer,
+                &paid.denom,
+                paid_amount,
+                funds,
+                total_tokens,
+            )?,
+            None => 0,
+        };
+        let pay_reward = match REFERRAL_CONFIG.load(deps.storage)?.reward_denom {
+            ReferralRewardDenom::Pay => referral_reward,
+            ReferralRewardDenom::Sale => 0,
+        };
+        RAISED.update(deps.storage, &paid.denom, |raised| {
+            let mut raised = raised.unwrap_or_default();
+            raised.total += paid_amount;
+            raised.contributed += paid_amount;
+            raised.contributed_net += paid_amount - fees - pay_reward;
+            raised.pending_fees += fees;
+            raised.pending_rewards += pay_reward;
+            StdResult::Ok(raised)
+        })?;
+        let first_contribution = CONTRIBUTIONS
+            .prefix(&buyer)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .next()
+            .is_none();
+        if first_contribution {
+            sale_data.contributors += 1;
+        }
+        CONTRIBUTIONS.update(deps.storage, (&buyer, &paid.denom), |contribution| {
+            let mut contribution = contribution.unwrap_or_default();
+            contribution.paid += paid_amount;
+            contribution.net += paid_amount - fees - pay_reward;
+            StdResult::Ok(contribution)
+        })?;
+        // sucessfull buy
+        sale_data.total_spent += used;
+        sale_data.total_tokens_sold += total_tokens;
+        sale_data.dust += quote.dust;
+        sale_data.allocation_spent += used;
+        sale_data.buys += 1;
+
+        // selling out ends the sale, late buys push a timed end back
+        let mut extended_end = None;
+        if sale_data.total_spent >= sale.max_supply {
+            sale.end_now(&env.block);
+            sale_data.sold_out_at = Some(env.block.time);
+            SALE_CONFIG.save(deps.storage, &sale)?;
+        } else if let Some(end) = sale.extend_end(&env.block) {
+            extended_end = Some(end);
+            SALE_CONFIG.save(deps.storage, &sale)?;
+        }
+        SALE_DATA.save(deps.storage, &sale_data)?;
+        match &token_id {
+            // bought tokens are claimed by the buyer, not the token
+            Some(token_id) => {
+                NFT_ALLOCATIONS.save(
+                    deps.storage,
+                    token_id,
+                    &UserInfo {
+                        allocation: user.allocation,
+                        spent: user.spent + used,
+                        received_amount: user.received_amount + total_tokens,
+                        ..Default::default()
+                    },
+                )?;
+                let mut buyer_info = USER_LIST
+                    .may_load(deps.storage, &buyer)?
+                    .unwrap_or_default();
+                buyer_info.received_amount += total_tokens;
+                buyer_info.bought += used;
+                USER_LIST.save(deps.storage, &buyer, &buyer_info)?;
+            }
+            None => USER_LIST.save(
+                deps.storage,
+                &buyer,
+                &UserInfo {
+                    allocation: user.allocation,
+                    spent: user.spent + used,
+                    received_amount: user.received_amount + total_tokens,
+                    price: user.price.clone(),
+                    bought: user.bought + used,
+                },
+            )?,
+        }
+        activity.buys += 1;
+        activity.next_buy = next_buy;
+        BUYER_ACTIVITY.save(deps.storage, &buyer, &activity)?;
+        BLOCK_VOLUME.save(
+            deps.storage,
+            &BlockVolume {
+                height: env.block.height,
+                amount: block_bought + funds,
+            },
+        )?;
+
+        let mut res = Response::new()
+            .add_attribute("action", "buy")
+            .add_attribute("address", buyer.clone())
+            .add_attribute("sender", info.sender.clone())
+            .add_attribute("amount", paid.to_string())
+            .add_attribute("filled", coin(paid_amount, &paid.denom).to_string())
+            .add_attribute("refunded", coin(refund, &paid.denom).to_string())
+            .add_attribute("normalized_amount", funds.to_string())
+            .add_attribute("tokens_bought", total_tokens.to_string())
+            .add_attribute("dust", quote.dust.to_string())
+            .add_attribute("fees", fees.to_string());
+        if let Some(referrer) = referrer {
+            res = res
+                .add_attribute("referrer", referrer)
+                .add_attribute("referral_reward", referral_reward.to_string());
+        }
+        if sale_data.sold_out_at.is_some() {
+            res = res.add_attribute("sold_out", "true");
+        }
+        if let Some(end) = extended_end {
+            res = res.add_attribute("end_extended", end.to_string());
+        }
+        if refund > 0 {
+            res = res.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: coins(refund, paid.denom),
+            });
+        }
+
+        Ok(res)
+    }
+
+    pub fn finalize(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        let state = STATE.load(deps.storage)?;
+        state.check_owner(&info.sender)?;
+        let owner = state.owner;
+
+        check_not_cancelled(deps.storage)?;
+
+        let sale = SALE_CONFIG.load(deps.storage)?;
+        if !sale.is_ended(&env.block) {
+            return Err(ContractError::SaleNotEnded {});
+        }
+
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        if sale_data.finalized_at.is_some() {
+            return Err(ContractError::AlreadyFinalized {});
+        }
+        sale_data.finalized_at = Some(env.block.time);
+        SALE_DATA.save(deps.storage, &sale_data)?;
+
+        // mint exactly what is owed, then hand the denom over to the owner
+        let mut messages = vec![];
+        if let Some(denom) = TOKEN_FACTORY_DENOM.may_load(deps.storage)? {
+            let owed = sale_data.tokens_owed();
+            if owed > 0 {
+                messages.push(create_mint_tokens_msg(
+                    env.contract.address.clone(),
+                    Coin::new(owed, &denom),
+                    env.contract.address.to_string(),
+                ));
+            }
+            messages.push(change_admin_msg(&env.contract.address, &denom, &owner));
+        }
+
+        Ok(Response::new()
+            .add_messages(messages)
+            .add_attribute("action", "finalize")
+            .add_attribute("tokens_owed", sale_data.tokens_owed().to_string()))
+    }
+
+    pub fn claim(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        if sale_data.finalized_at.is_none() {
+            return Err(ContractError::NotFinalized {});
+        }
+
+        let denom = SALE_CONFIG
+            .load(deps.storage)?
+            .sale_denom
+            .ok_or(ContractError::NoSaleDenom {})?;
+
+        let claim = query::claim(deps.as_ref(), info.sender.to_string())?;
+        let amount = claim.owed - claim.claimed;
+        if amount == 0 {
+            return Err(ContractError::NothingToClaim {});
+        }
+
+        CLAIMED.save(deps.storage, &info.sender, &claim.owed)?;
+        sale_data.total_claimed += amount;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+
+        Ok(Response::new()
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: coins(amount, denom),
+            })
+            .add_attribute("action", "claim")
+            .add_attribute("address", info.sender)
+            .add_attribute("amount", amount.to_string()))
+    }
+
+    pub fn update_registration_config(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        info: MessageInfo,
+        config: Option<RegistrationConfig>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        // only owner
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        // terms are fixed once users registered
+        let data = REGISTRATION_DATA
+            .may_load(deps.storage)?
+            .unwrap_or_default();
+        if data.registrants > 0 {
+            return Err(ContractError::RegistrationLocked {});
+        }
+
+        match config {
+            Some(config) => {
+                config.validate(&SALE_CONFIG.load(deps.storage)?)?;
+                REGISTRATION_CONFIG.save(deps.storage, &config)?;
+                REGISTRATION_DATA.save(deps.storage, &data)?;
+            }
+            None => REGISTRATION_CONFIG.remove(deps.storage),
+        }
+
+        Ok(Response::new().add_attribute("action", "update_registration_config"))
+    }
+
+    pub fn register(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+        weight: Option<u128>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let config = REGISTRATION_CONFIG
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoRegistration {})?;
+
+        // only while open, and once
+        if !config.is_open(&env.block) {
+            return Err(ContractError::RegistrationNotOpen {});
+        }
+        if REGISTRATIONS.has(deps.storage, &info.sender) {
+            return Err(ContractError::AlreadyRegistered {});
+        }
+
+        // holders escrow their holding, so the same tokens can't register twice
+        let mut funds = info.funds;
+        let holding = match &config.min_holding {
+            Some(required) => {
+                let holding = funds
+                    .iter()
+                    .position(|coin| coin.denom == required.denom)
+                    .map(|index| funds.remove(index))
+                    .filter(|coin| coin.amount >= required.amount)
+                    .ok_or(ContractError::InsufficientHoldings {
+                        required: required.to_string(),
+                    })?;
+                Some(holding)
+            }
+            None => None,
+        };
+
+        // and pay exactly the deposit, if any
+        let expected = config.deposit.iter().cloned().collect::<Vec<_>>();
+        if funds != expected {
+            return Err(ContractError::InvalidDeposit {
+                deposit: config
+                    .deposit
+                    .as_ref()
+                    .map_or("nothing".to_string(), |coin| coin.to_string()),
+            });
+        }
+
+        // the weighted rule takes the given weight, as far as the escrow backs it
+        let escrowed = holding.as_ref().map_or(0, |coin| coin.amount.u128());
+        let weight = match config.rule {
+            AllocationRule::Weighted => weight
+                .filter(|weight| *weight > 0 && *weight <= escrowed)
+                .ok_or(ContractError::InvalidWeight {})?,
+            _ => 1,
+        };
+        let mut data = REGISTRATION_DATA.load(deps.storage)?;
+        data.total_weight = data
+            .total_weight
+            .checked_add(weight)
+            .ok_or(ContractError::InvalidWeight {})?;
+        data.registrants += 1;
+        data.deposits += config.deposit.as_ref().map_or(0, |coin| coin.amount.u128());
+        data.holdings += escrowed;
+
+        REGISTRATIONS.save(
+            deps.storage,
+            &info.sender,
+            &Registration {
+                weight,
+                deposit: config.deposit,
+                allocation: None,
+                holding,
+            },
+        )?;
+        REGISTRATION_DATA.save(deps.storage, &data)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "register")
+            .add_attribute("address", info.sender)
+            .add_attribute("weight", weight.to_string()))
+    }
+
+    pub fn unregister(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let config = REGISTRATION_CONFIG
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoRegistration {})?;
+
+        // only while open
+        if !config.is_open(&env.block) {
+            return Err(ContractError::RegistrationNotOpen {});
+        }
+        let registration = REGISTRATIONS
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(ContractError::NotRegistered {})?;
+
+        REGISTRATIONS.remove(deps.storage, &info.sender);
+        REGISTRATION_DATA.update(deps.storage, |mut data| -> StdResult<_> {
+            data.registrants -= 1;
+            data.total_weight -= registration.weight;
+            data.deposits -= registration
+                .deposit
+                .as_ref()
+                .map_or(0, |coin| coin.amount.u128());
+            data.holdings -= registration
+                .holding
+                .as_ref()
+                .map_or(0, |coin| coin.amount.u128());
+            Ok(data)
+        })?;
+
+        let mut res = Response::new()
+            .add_attribute("action", "unregister")
+            .add_attribute("address", info.sender.clone());
+        let returned = registration
+            .deposit
+            .into_iter()
+            .chain(registration.holding)
+            .collect::<Vec<_>>();
+        if !returned.is_empty() {
+            res = res.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: returned,
+            });
+        }
+
+        Ok(res)
+    }
+
+    // Anyone may apply a split rule, the rule alone decides the allocations
+    pub fn allocate_registrations(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        limit: Option<u32>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let config = REGISTRATION_CONFIG
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoRegistration {})?;
+
+        // only once closed, and only under a split rule
+        if !config.is_closed(&env.block) {
+            return Err(ContractError::RegistrationNotClosed {});
+        }
+        if config.rule == AllocationRule::Manual {
+            return Err(ContractError::ManualAllocation {});
+        }
+
+        let mut data = REGISTRATION_DATA.load(deps.storage)?;
+        let limit = limit
+            .unwrap_or(DEFAULT_ALLOCATE_LIMIT)
+            .min(MAX_ALLOCATE_LIMIT) as usize;
+        let page = REGISTRATIONS
+            .range(
+                deps.storage,
+                data.last_allocated.as_ref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+        if page.is_empty() {
+            return Err(ContractError::NothingToAllocate {});
+        }
+
+        // registrants keep any allocation they already had
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        for (address, mut registration) in page {
+            let share = data.share(&config, registration.weight);
+            let mut user = USER_LIST
+                .may_load(deps.storage, &address)?
+                .unwrap_or_default();
+            user.allocation += share;
+            USER_LIST.save(deps.storage, &address, &user)?;
+            sale_data.total_allocated += share;
+
+            registration.allocation = Some(share);
+            REGISTRATIONS.save(deps.storage, &address, &registration)?;
+            data.allocated += 1;
+            data.last_allocated = Some(address);
+        }
+        check_allocated(&SALE_CONFIG.load(deps.storage)?, &sale_data)?;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+        REGISTRATION_DATA.save(deps.storage, &data)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "allocate_registrations")
+            .add_attribute("allocated", data.allocated.to_string())
+            .add_attribute("registrants", data.registrants.to_string()))
+    }
+
+    pub fn return_deposit(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let mut registration = REGISTRATIONS
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(ContractError::NotRegistered {})?;
 
-    use crate::{
-        msg::UserInfoReq,
-        state::{UserInfo, USER_LIST},
-    };
+        // only once the sale is over
+        let ended = SALE_CONFIG.load(deps.storage)?.is_ended(&env.block);
+        if !ended && !CANCELLATION.exists(deps.storage) {
+            return Err(ContractError::DepositLocked {});
+        }
 
-    use super::*;
+        // the escrowed holding comes back with the deposit
+        let deposit = registration.deposit.take();
+        let holding = registration.holding.take();
+        if deposit.is_none() && holding.is_none() {
+            return Err(ContractError::NothingToClaim {});
+        }
+        REGISTRATIONS.save(deps.storage, &info.sender, &registration)?;
+        REGISTRATION_DATA.update(deps.storage, |mut data| -> StdResult<_> {
+            data.deposits -= deposit.as_ref().map_or(0, |coin| coin.amount.u128());
+            data.holdings -= holding.as_ref().map_or(0, |coin| coin.amount.u128());
+            Ok(data)
+        })?;
 
-    pub fn transfer_ownership(
-        deps: DepsMut,
+        let returned = deposit.into_iter().chain(holding).collect::<Vec<_>>();
+        Ok(Response::new()
+            .add_attribute("action", "return_deposit")
+            .add_attribute("address", info.sender.clone())
+            .add_attribute(
+                "returned",
+                returned
+                    .iter()
+                    .map(Coin::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: returned,
+            }))
+    }
+
+    pub fn cancel_sale(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
         info: MessageInfo,
-        address: String,
-    ) -> Result<Response, ContractError> {
+        reason: String,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
         // only owner
-        let owner = STATE.load(deps.storage)?.owner;
-        if info.sender != owner {
-            return Err(ContractError::Unauthorized {});
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        // only once, and not after tokens became claimable
+        check_not_cancelled(deps.storage)?;
+        if SALE_DATA.load(deps.storage)?.finalized_at.is_some() {
+            return Err(ContractError::AlreadyFinalized {});
         }
 
-        let new_owner = deps.api.addr_validate(&address)?;
-        STATE.save(
+        CANCELLATION.save(
             deps.storage,
-            &State {
-                owner: new_owner.clone(),
+            &Cancellation {
+                reason: reason.clone(),
+                cancelled_at: env.block.time,
+                refunded_users: 0,
             },
         )?;
 
         Ok(Response::new()
-            .add_attribute("action", "transfer_ownership")
-            .add_attribute("from", info.sender)
-            .add_attribute("to", new_owner))
+            .add_attribute("action", "cancel_sale")
+            .add_attribute("reason", reason))
     }
 
-    pub fn update_sale(
-        deps: DepsMut,
+    // Returns what `address` paid in each denom and clears it
+    fn refund_contributions(
+        storage: &mut dyn Storage,
+        cancellation: &mut Cancellation,
+        address: &Addr,
+        contributions: Vec<(String, Contribution)>,
+    ) -> StdResult<BankMsg> {
+        let mut amount = vec![];
+        for (denom, contribution) in contributions {
+            CONTRIBUTIONS.remove(storage, (address, &denom));
+            RAISED.update(storage, &denom, |raised| {
+                let mut raised = raised.unwrap_or_default();
+                raised.refunded += contribution.paid;
+                raised.contributed -= contribution.paid;
+                raised.contributed_net -= contribution.net;
+                StdResult::Ok(raised)
+            })?;
+            amount.push(coin(contribution.paid, denom));
+        }
+
+        let done = CONTRIBUTIONS
+            .prefix(address)
+            .keys(storage, None, None, Order::Ascending)
+            .next()
+            .is_none();
+        if done {
+            cancellation.refunded_users += 1;
+        }
+
+        Ok(BankMsg::Send {
+            to_address: address.to_string(),
+            amount,
+        })
+    }
+
+    pub fn refund(
+        deps: DepsMut<InjectiveQueryWrapper>,
         info: MessageInfo,
-        sale: SaleConfig,
-    ) -> Result<Response, ContractError> {
-        // only owner
-        let owner = STATE.load(deps.storage)?.owner;
-        if info.sender != owner {
-            return Err(ContractError::Unauthorized {});
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let mut cancellation = CANCELLATION
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NotCancelled {})?;
+
+        let contributions = CONTRIBUTIONS
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        if contributions.is_empty() {
+            return Err(ContractError::NothingToRefund {});
         }
 
-        SALE_CONFIG.save(deps.storage, &sale)?;
-        Ok(Response::new().add_attribute("action", "update_sale"))
+        let msg =
+            refund_contributions(deps.storage, &mut cancellation, &info.sender, contributions)?;
+        CANCELLATION.save(deps.storage, &cancellation)?;
+
+        Ok(Response::new()
+            .add_message(msg)
+            .add_attribute("action", "refund")
+            .add_attribute("address", info.sender))
     }
 
-    pub fn set_user_list(
-        deps: DepsMut,
+    pub fn push_refunds(
+        deps: DepsMut<InjectiveQueryWrapper>,
         info: MessageInfo,
-        users: Vec<UserInfoReq>,
-    ) -> Result<Response, ContractError> {
+        limit: Option<u32>,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
         // only owner
-        let owner = STATE.load(deps.storage)?.owner;
-        if info.sender != owner {
-            return Err(ContractError::Unauthorized {});
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        let mut cancellation = CANCELLATION
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NotCancelled {})?;
+
+        // refunded contributions are removed, so every page starts from the first one left
+        let limit = limit.unwrap_or(DEFAULT_REFUND_LIMIT).min(MAX_REFUND_LIMIT) as usize;
+        let page = CONTRIBUTIONS
+            .range(deps.storage, None, None, Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        let mut by_buyer: Vec<(Addr, Vec<(String, Contribution)>)> = vec![];
+        for ((address, denom), contribution) in page {
+            match by_buyer.last_mut() {
+                Some((last, contributions)) if *last == address => {
+                    contributions.push((denom, contribution))
+                }
+                _ => by_buyer.push((address, vec![(denom, contribution)])),
+            }
         }
 
-        for user in users {
-            let addr = deps.api.addr_validate(&user.address)?;
-            USER_LIST.save(
+        let mut messages = vec![];
+        for (address, contributions) in &by_buyer {
+            messages.push(refund_contributions(
                 deps.storage,
-                &addr,
-                &UserInfo {
-                    allocation: user.allocation,
-                    spent: user.spent,
-                    received_amount: 0,
-                },
-            )?;
+                &mut cancellation,
+                address,
+                contributions.clone(),
+            )?);
         }
-        Ok(Response::new().add_attribute("action", "set_user_list"))
-    }
+        CANCELLATION.save(deps.storage, &cancellation)?;
 
-    // TODO: handle price and add receive_token
-    pub fn buy(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
-        // only if sale is active
-        let sale = SALE_CONFIG.load(deps.storage)?;
-        if !sale.is_active(env.block.time) {
-            return Err(ContractError::SaleNotActive {});
-        }
+        Ok(Response::new()
+            .add_messages(messages)
+            .add_attribute("action", "push_refunds")
+            .add_attribute("buyers", by_buyer.len().to_string()))
+    }
 
-        // only correct funds
-        let funds = must_pay(&info, &sale.pay_denom)
-            .map_err(|_| ContractError::MissingFunds {})?
-            .u128();
+    pub fn claim_insurance(
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
+        let config = INSURANCE_CONFIG
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoInsurance {})?;
 
-        let user = USER_LIST.load(deps.storage, &info.sender)?;
+        // only during the window after finalize
+        let mut sale_data = SALE_DATA.load(deps.storage)?;
+        let finalized_at = sale_data
+            .finalized_at
+            .ok_or(ContractError::NotFinalized {})?;
+        if config.is_closed(Some(finalized_at), env.block.time) {
+            return Err(ContractError::InsuranceClosed {});
+        }
 
-        // only if user is in list and has available allocation
-        if user.allocation == 0 {
-            return Err(ContractError::NotParticipating {});
+        // only bought tokens that were never claimed go back
+        let mut user = USER_LIST
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(ContractError::NothingToReturn {})?;
+        let claim = query::claim(deps.as_ref(), info.sender.to_string())?;
+        let returned = (claim.owed - claim.claimed).min(user.received_amount);
+        if returned == 0 {
+            return Err(ContractError::NothingToReturn {});
         }
 
-        // only if user has enough allocation left
-        if funds > user.available_allocation() {
-            return Err(ContractError::UserAllocationExceeded {
-                wanted: funds,
-                max: user.available_allocation(),
-            });
+        // the larger of the shares that apply right now
+        let elapsed = env.block.time.seconds() - finalized_at.seconds();
+        let mut share = if elapsed < config.unconditional_window {
+            config.unconditional_share
+        } else {
+            Decimal::zero()
+        };
+        if let Some(oracle_config) = &config.oracle {
+            if config.below_price_share > share {
+                let sale = SALE_CONFIG.load(deps.storage)?;
+                let (price_pay_amount, price_receive_amount) = match &user.price {
+                    Some(price) => (price.price_pay_amount, price.price_receive_amount),
+                    None => (sale.price_pay_amount, sale.price_receive_amount),
+                };
+                // both per whole token, the config requires decimals with an oracle
+                let usd_price = oracle::usd_price(&deps.querier, env.block.time, oracle_config)?;
+                if usd_price < Decimal256::from_ratio(price_pay_amount, price_receive_amount) {
+                    share = config.below_price_share;
+                }
+            }
         }
 
-        let sale_data = SALE_DATA.load(deps.storage)?;
-        let potential_amount: u128 = sale_data.total_spent + funds;
+        // pays back the share of what the raise kept for the returned tokens, in the denoms
+        // paid, fees and referral rewards on it are still paid out
+        let contributions = CONTRIBUTIONS
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        let mut amount = vec![];
+        for (denom, mut contribution) in contributions {
+            let paid_back = Uint128::from(contribution.paid)
+                .multiply_ratio(returned, user.received_amount)
+                .u128();
+            let net_back = Uint128::from(contribution.net)
+                .multiply_ratio(returned, user.received_amount)
+                .u128();
+            contribution.paid -= paid_back;
+            contribution.net -= net_back;
+            CONTRIBUTIONS.save(deps.storage, (&info.sender, &denom), &contribution)?;
 
-        // only if sale has enough allocation left
-        if potential_amount > sale.max_supply {
-            return Err(ContractError::SaleAllocationExceeded {
-                wanted: funds,
-                max: sale.max_supply - sale_data.total_spent,
-            });
+            let payout = Uint128::from(net_back).mul_floor(share).u128();
+            RAISED.update(deps.storage, &denom, |raised| {
+                let mut raised = raised.unwrap_or_default();
+                raised.refunded += payout;
+                raised.contributed -= paid_back;
+                raised.contributed_net -= net_back;
+                StdResult::Ok(raised)
+            })?;
+            if payout > 0 {
+                amount.push(coin(payout, denom));
+            }
+        }
+        if amount.is_empty() {
+            return Err(ContractError::NothingToRefund {});
         }
 
-        let total_tokens = query::receive_amount(deps.as_ref(), funds)?;
-        // sucessfull buy
-        SALE_DATA.save(
-            deps.storage,
-            &SaleData {
-                total_spent: potential_amount,
-            },
-        )?;
-        USER_LIST.save(
-            deps.storage,
-            &info.sender,
-            &UserInfo {
-                allocation: user.allocation,
-                spent: user.spent + funds,
-                received_amount: user.received_amount + total_tokens,
-            },
-        )?;
+        user.received_amount -= returned;
+        USER_LIST.save(deps.storage, &info.sender, &user)?;
+        sale_data.tokens_returned += returned;
+        SALE_DATA.save(deps.storage, &sale_data)?;
+
+        let refunded = amount
+            .iter()
+            .map(|coin| coin.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
 
         Ok(Response::new()
-            .add_attribute("action", "buy")
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount,
+            })
+            .add_attribute("action", "claim_insurance")
             .add_attribute("address", info.sender)
-            .add_attribute("amount", funds.to_string())
-            .add_attribute("tokens_bought", total_tokens.to_string()))
+            .add_attribute("tokens_returned", returned.to_string())
+            .add_attribute("share", share.to_string())
+            .add_attribute("refunded", refunded))
     }
 
     pub fn withdraw(
-        deps: DepsMut,
+        deps: DepsMut<InjectiveQueryWrapper>,
+        env: Env,
         info: MessageInfo,
-        to: String,
         funds: Vec<Coin>,
-    ) -> Result<Response, ContractError> {
+    ) -> Result<Response<InjectiveMsgWrapper>, ContractError> {
         // only owner
-        let owner = STATE.load(deps.storage)?.owner;
-        if info.sender != owner {
-            return Err(ContractError::Unauthorized {});
+        STATE.load(deps.storage)?.check_owner(&info.sender)?;
+
+        // raised denoms wait until buyers can no longer be refunded in full
+        if !CANCELLATION.exists(deps.storage) {
+            for coin in &funds {
+                if RAISED.has(deps.storage, &coin.denom) {
+                    check_finalized(deps.storage)?;
+                }
+            }
+        }
+
+        // pending fees can't be withdrawn
+        for coin in &funds {
+            let available = query::withdrawable(deps.as_ref(), &env, &coin.denom)?;
+            if coin.amount.u128() > available {
+                return Err(ContractError::WithdrawExceedsAvailable {
+                    denom: coin.denom.clone(),
+                    available,
+                });
+            }
+        }
+
+        for coin in &funds {
+            if let Some(mut raised) = RAISED.may_load(deps.storage, &coin.denom)? {
+                raised.withdrawn += coin.amount.u128();
+                RAISED.save(deps.storage, &coin.denom, &raised)?;
+            }
         }
+        let raised = query::raised(deps.as_ref(), &env)?
+            .iter()
+            .map(|raised| coin(raised.total, &raised.denom).to_string())
+            .collect::<Vec<_>>()
+            .join(",");
 
+        // funds always go to the treasury
+        let treasury = ROLES.load(deps.storage)?.treasury;
         let bank_msg = BankMsg::Send {
-            to_address: deps.api.addr_validate(&to)?.to_string(),
+            to_address: treasury.to_string(),
             amount: funds,
         };
 
         Ok(Response::new()
             .add_message(bank_msg)
             .add_attribute("action", "withdraw")
-            .add_attribute("to", to))
+            .add_attribute("to", treasury)
+            .add_attribute("raised", raised))
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps<InjectiveQueryWrapper>, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::GetSale {} => to_json_binary(&query::sale(deps)?),
+        QueryMsg::GetState {} => to_json_binary(&STATE.load(deps.storage)?),
+        QueryMsg::GetSale {} => to_json_binary(&query::sale(deps, &env)?),
+        QueryMsg::GetStatus {} => to_json_binary(&query::status(deps, &env)?),
+        QueryMsg::SimulateBuy { address, amount } => {
+            to_json_binary(&query::simulate_buy(deps, &env, address, amount)?)
+        }
         QueryMsg::GetUserInfo { address } => to_json_binary(&query::user(deps, address)?),
         QueryMsg::GetUsers {} => to_json_binary(&query::users(deps)?),
-        QueryMsg::GetReceivedAmount { pay_amount } => {
-            to_json_binary(&query::receive_amount(deps, pay_amount)?)
+        QueryMsg::GetReceivedAmount {
+            pay_amount,
+            denom,
+            address,
+        } => to_json_binary(&query::receive_amount(
+            deps, &env, pay_amount, denom, address,
+        )?),
+        QueryMsg::GetRoles {} => to_json_binary(&query::roles(deps)?),
+        QueryMsg::GetFees {} => to_json_binary(&query::fees(deps)?),
+        QueryMsg::GetReferralConfig {} => to_json_binary(&REFERRAL_CONFIG.load(deps.storage)?),
+        QueryMsg::GetBeneficiaryConfig {} => {
+            to_json_binary(&BENEFICIARY_CONFIG.load(deps.storage)?)
+        }
+        QueryMsg::GetAntiBotConfig {} => to_json_binary(&ANTI_BOT_CONFIG.load(deps.storage)?),
+        QueryMsg::GetAllocationNft {} => to_json_binary(&ALLOCATION_NFT.may_load(deps.storage)?),
+        QueryMsg::GetNftAllocation { token_id } => {
+            to_json_binary(&query::nft_allocation(deps, token_id)?)
+        }
+        QueryMsg::GetTransferConfig {} => to_json_binary(&TRANSFER_CONFIG.load(deps.storage)?),
+        QueryMsg::GetFcfs {} => to_json_binary(&query::fcfs(deps, &env)?),
+        QueryMsg::GetUserLimits { address } => {
+            to_json_binary(&query::user_limits(deps, &env, address)?)
+        }
+        QueryMsg::GetReferrer { address } => to_json_binary(&query::referrer(deps, address)?),
+        QueryMsg::GetTopReferrers { limit } => to_json_binary(&query::top_referrers(deps, limit)?),
+        QueryMsg::GetBuyerStats {} => to_json_binary(&query::buyer_stats(deps)?),
+        QueryMsg::GetTopBuyers { limit } => to_json_binary(&query::top_buyers(deps, limit)?),
+        QueryMsg::GetClaim { address } => to_json_binary(&query::claim(deps, address)?),
+        QueryMsg::GetRaised {} => to_json_binary(&query::raised(deps, &env)?),
+        QueryMsg::GetRefundStatus {} => to_json_binary(&query::refund_status(deps)?),
+        QueryMsg::GetRefund { address } => to_json_binary(&query::refund(deps, address)?),
+        QueryMsg::GetInsurance {} => to_json_binary(&query::insurance(deps, &env)?),
+        QueryMsg::GetRegistration {} => to_json_binary(&query::registration(deps, &env)?),
+        QueryMsg::GetRegistrant { address } => to_json_binary(&query::registrant(deps, address)?),
+        QueryMsg::GetRegistrants { start_after, limit } => {
+            to_json_binary(&query::registrants(deps, start_after, limit)?)
         }
     }
 }
 
 pub mod query {
-    use cosmwasm_std::{Addr, Order};
+    use std::collections::BTreeMap;
+
+    use cosmwasm_std::{coin, Addr, Coin, Decimal, Order, StdError, Uint128};
+    use cw721::{Cw721QueryMsg, OwnerOfResponse};
+    use cw_storage_plus::Bound;
+    use cw_utils::{Duration, Expiration, Scheduled};
 
     use crate::{
-        msg::{GetSaleResponse, UserInfoRes},
-        state::{UserInfo, USER_LIST},
+        msg::{
+            BuyerStatsRes, ClaimRes, FcfsRes, FeeRecipientRes, GetRolesResponse, GetSaleResponse,
+            InsuranceRes, NftAllocationRes, RaisedRes, ReferrerRes, RefundStatusRes, RegistrantRes,
+            RegistrationRes, SalePhase, SimulateBuyRes, StatusRes, UserInfoRes, UserLimitsRes,
+        },
+        oracle,
+        state::{
+            PayDenom, PriceOverride, ReferralInfo, Registration, UserInfo, BLOCK_VOLUME,
+            BUYER_ACTIVITY, CANCELLATION, CLAIMED, CONTRIBUTIONS, FCFS_CONFIG, FEES,
+            NFT_ALLOCATIONS, PENDING_OWNER, REFERRALS, REFERRAL_REWARDS, USER_LIST,
+        },
     };
 
     use super::*;
 
-    pub fn receive_amount(deps: Deps, pay_amount: u128) -> StdResult<u128> {
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 100;
+
+    pub struct Quote {
+        pub value: u128,
+        pub tokens: u128,
+        pub dust: u128,
+    }
+
+    impl Quote {
+        // what the buy counts against allocations and supply
+        pub fn used(&self, unit: &CapUnit) -> u128 {
+            match unit {
+                CapUnit::Pay => self.value,
+                CapUnit::Tokens => self.tokens,
+            }
+        }
+    }
+
+    // Anti-bot limits as they apply to `address` right now
+    pub fn user_limits(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        address: String,
+    ) -> StdResult<UserLimitsRes> {
+        let addr = deps.api.addr_validate(&address)?;
+        let sale = SALE_CONFIG.load(deps.storage)?;
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let anti_bot = ANTI_BOT_CONFIG.load(deps.storage)?;
+        let activity = BUYER_ACTIVITY
+            .may_load(deps.storage, &addr)?
+            .unwrap_or_default();
+        let block_bought = BLOCK_VOLUME
+            .may_load(deps.storage)?
+            .unwrap_or_default()
+            .at(&env.block);
+
+        Ok(UserLimitsRes {
+            address,
+            available_allocation: match USER_LIST.may_load(deps.storage, &addr)? {
+                Some(user) => allocation_limit(deps, env, &sale, &sale_data, &user)?,
+                None => 0,
+            },
+            max_per_tx: anti_bot.max_per_tx,
+            block_available: anti_bot
+                .max_per_block
+                .map(|max| max.saturating_sub(block_bought)),
+            buys: activity.buys,
+            max_buys: anti_bot.max_buys,
+            next_buy: activity
+                .next_buy
+                .filter(|next_buy| !next_buy.is_expired(&env.block)),
+        })
+    }
+
+    // What `user` may still spend: their allocation, or in the FCFS phase up to the
+    // multiplied allocation as long as the pool lasts
+    pub fn allocation_limit(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        sale: &SaleConfig,
+        sale_data: &SaleData,
+        user: &UserInfo,
+    ) -> StdResult<u128> {
+        let fcfs = FCFS_CONFIG
+            .may_load(deps.storage)?
+            .filter(|fcfs| fcfs.is_active(&env.block));
+
+        Ok(match fcfs {
+            Some(fcfs) => fcfs
+                .cap(user.allocation)
+                .saturating_sub(user.spent)
+                .min(sale_data.fcfs_pool(sale.max_supply)),
+            None => user.available_allocation(),
+        })
+    }
+
+    pub fn fcfs(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<FcfsRes> {
+        let sale = SALE_CONFIG.load(deps.storage)?;
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let config = FCFS_CONFIG.may_load(deps.storage)?;
+
+        Ok(FcfsRes {
+            active: config
+                .as_ref()
+                .is_some_and(|config| config.is_active(&env.block)),
+            config,
+            pool: sale_data.fcfs_pool(sale.max_supply),
+        })
+    }
+
+    // Fails unless `owner` holds `token_id` of the allocation NFT collection
+    pub fn check_nft_owner(
+        deps: Deps<InjectiveQueryWrapper>,
+        token_id: &str,
+        owner: &Addr,
+    ) -> Result<(), ContractError> {
+        let collection = ALLOCATION_NFT
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoAllocationNft {})?;
+        let res: OwnerOfResponse = deps.querier.query_wasm_smart(
+            collection,
+            &Cw721QueryMsg::OwnerOf {
+                token_id: token_id.to_string(),
+                include_expired: None,
+            },
+        )?;
+        if res.owner != *owner {
+            return Err(ContractError::NftNotOwned {
+                token_id: token_id.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn nft_allocation(
+        deps: Deps<InjectiveQueryWrapper>,
+        token_id: String,
+    ) -> StdResult<NftAllocationRes> {
+        let allocation = NFT_ALLOCATIONS.load(deps.storage, &token_id)?;
+
+        Ok(NftAllocationRes {
+            token_id,
+            allocation: allocation.allocation,
+            spent: allocation.spent,
+            received_amount: allocation.received_amount,
+        })
+    }
+
+    // Bought tokens plus sale token referral rewards
+    pub fn claim(deps: Deps<InjectiveQueryWrapper>, address: String) -> StdResult<ClaimRes> {
+        let addr = deps.api.addr_validate(&address)?;
+        let bought = USER_LIST
+            .may_load(deps.storage, &addr)?
+            .map_or(0, |user| user.received_amount);
+        let referral = REFERRALS
+            .may_load(deps.storage, &addr)?
+            .map_or(0, |info| info.token_reward);
+
+        Ok(ClaimRes {
+            address,
+            owed: bought + referral,
+            claimed: CLAIMED.may_load(deps.storage, &addr)?.unwrap_or(0),
+        })
+    }
+
+    // Contract balance of `denom` that isn't reserved for fee recipients or claims
+    pub fn withdrawable(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        denom: &str,
+    ) -> StdResult<u128> {
+        let balance = deps
+            .querier
+            .query_balance(&env.contract.address, denom)?
+            .amount
+            .u128();
+
+        // registration deposits and escrowed holdings go back to registrants
+        let registered = match REGISTRATION_CONFIG.may_load(deps.storage)? {
+            Some(config) => {
+                let data = REGISTRATION_DATA.load(deps.storage)?;
+                let deposits = match config.deposit {
+                    Some(deposit) if deposit.denom == denom => data.deposits,
+                    _ => 0,
+                };
+                let holdings = match config.min_holding {
+                    Some(holding) if holding.denom == denom => data.holdings,
+                    _ => 0,
+                };
+                deposits + holdings
+            }
+            None => 0,
+        };
+        let balance = balance.saturating_sub(registered);
+
+        // a cancelled sale owes buyers what they paid, fees and rewards included
+        let raised = RAISED.may_load(deps.storage, denom)?.unwrap_or_default();
+        if CANCELLATION.exists(deps.storage) {
+            return Ok(balance.saturating_sub(raised.contributed));
+        }
+
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let unclaimed = match SALE_CONFIG.load(deps.storage)?.sale_denom {
+            Some(sale_denom) if sale_denom == denom => {
+                sale_data.tokens_owed() - sale_data.total_claimed
+            }
+            _ => 0,
+        };
+
+        // the most the insurance could still pay back
+        let insured = match INSURANCE_CONFIG.may_load(deps.storage)? {
+            Some(config) if !config.is_closed(sale_data.finalized_at, env.block.time) => {
+                Uint128::from(raised.contributed_net)
+                    .mul_ceil(config.max_share())
+                    .u128()
+            }
+            _ => 0,
+        };
+
+        Ok(balance
+            .saturating_sub(raised.pending_fees + raised.pending_rewards + unclaimed + insured))
+    }
+
+    pub fn registration(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+    ) -> StdResult<RegistrationRes> {
+        let config = REGISTRATION_CONFIG.may_load(deps.storage)?;
+        let data = REGISTRATION_DATA
+            .may_load(deps.storage)?
+            .unwrap_or_default();
+
+        Ok(RegistrationRes {
+            open: config.as_ref().is_some_and(|c| c.is_open(&env.block)),
+            closed: config.as_ref().is_some_and(|c| c.is_closed(&env.block)),
+            config,
+            registrants: data.registrants,
+            total_weight: data.total_weight,
+            allocated: data.allocated,
+        })
+    }
+
+    fn registrant_res(address: Addr, registration: Registration) -> RegistrantRes {
+        RegistrantRes {
+            address: address.to_string(),
+            weight: registration.weight,
+            deposit: registration.deposit,
+            allocation: registration.allocation,
+            holding: registration.holding,
+        }
+    }
+
+    pub fn registrant(
+        deps: Deps<InjectiveQueryWrapper>,
+        address: String,
+    ) -> StdResult<Option<RegistrantRes>> {
+        let address = deps.api.addr_validate(&address)?;
+        Ok(REGISTRATIONS
+            .may_load(deps.storage, &address)?
+            .map(|registration| registrant_res(address, registration)))
+    }
+
+    pub fn registrants(
+        deps: Deps<InjectiveQueryWrapper>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<Vec<RegistrantRes>> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after
+            .map(|address| deps.api.addr_validate(&address))
+            .transpose()?;
+
+        REGISTRATIONS
+            .range(
+                deps.storage,
+                start_after.as_ref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .map(|item| item.map(|(address, registration)| registrant_res(address, registration)))
+            .collect()
+    }
+
+    pub fn insurance(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<InsuranceRes> {
+        let config = INSURANCE_CONFIG.may_load(deps.storage)?;
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let closes_at = config.as_ref().and_then(|config| {
+            sale_data
+                .finalized_at
+                .map(|finalized_at| finalized_at.plus_seconds(config.window))
+        });
+
+        Ok(InsuranceRes {
+            config,
+            open: closes_at.is_some_and(|closes_at| env.block.time < closes_at),
+            closes_at,
+            tokens_returned: sale_data.tokens_returned,
+        })
+    }
+
+    pub fn refund_status(deps: Deps<InjectiveQueryWrapper>) -> StdResult<RefundStatusRes> {
+        let cancellation = CANCELLATION.may_load(deps.storage)?;
+        let refunded_users = cancellation
+            .as_ref()
+            .map_or(0, |cancellation| cancellation.refunded_users);
+
+        let mut refunded = vec![];
+        let mut pending = vec![];
+        for item in RAISED.range(deps.storage, None, None, Order::Ascending) {
+            let (denom, raised) = item?;
+            if raised.refunded > 0 {
+                refunded.push(coin(raised.refunded, &denom));
+            }
+            if raised.contributed > 0 {
+                pending.push(coin(raised.contributed, &denom));
+            }
+        }
+
+        Ok(RefundStatusRes {
+            cancellation,
+            pending_users: SALE_DATA.load(deps.storage)?.contributors - refunded_users,
+            refunded,
+            pending,
+        })
+    }
+
+    pub fn refund(deps: Deps<InjectiveQueryWrapper>, address: String) -> StdResult<Vec<Coin>> {
+        let address = deps.api.addr_validate(&address)?;
+        CONTRIBUTIONS
+            .prefix(&address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, contribution)| coin(contribution.paid, denom)))
+            .collect()
+    }
+
+    fn referrer_res(
+        deps: Deps<InjectiveQueryWrapper>,
+        address: Addr,
+        info: ReferralInfo,
+    ) -> StdResult<ReferrerRes> {
+        let mut pending = vec![];
+        let mut claimed = vec![];
+        for item in
+            REFERRAL_REWARDS
+                .prefix(&address)
+                .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (denom, reward) = item?;
+            pending.push(coin(reward.pending, &denom));
+            claimed.push(coin(reward.settled, &denom));
+        }
+
+        Ok(ReferrerRes {
+            address: address.to_string(),
+            volume: info.volume,
+            buys: info.buys,
+            token_reward: info.token_reward,
+            pending,
+            claimed,
+        })
+    }
+
+    pub fn referrer(deps: Deps<InjectiveQueryWrapper>, address: String) -> StdResult<ReferrerRes> {
+        let address = deps.api.addr_validate(&address)?;
+        let info = REFERRALS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default();
+
+        referrer_res(deps, address, info)
+    }
+
+    pub fn top_referrers(
+        deps: Deps<InjectiveQueryWrapper>,
+        limit: Option<u32>,
+    ) -> StdResult<Vec<ReferrerRes>> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+        // the volume index returns referrers in order, largest first
+        REFERRALS
+            .idx
+            .volume
+            .range(deps.storage, None, None, Order::Descending)
+            .take(limit)
+            .map(|item| {
+                let (address, info) = item?;
+                referrer_res(deps, address, info)
+            })
+            .collect()
+    }
+
+    pub fn fees(deps: Deps<InjectiveQueryWrapper>) -> StdResult<Vec<FeeRecipientRes>> {
+        let mut balances: BTreeMap<Addr, FeeRecipientRes> = BTreeMap::new();
+
+        // configured recipients first, then anyone removed who still has a balance
+        let recipients = FEE_RECIPIENTS.load(deps.storage)?;
+        for recipient in &recipients {
+            balances.insert(
+                recipient.address.clone(),
+                FeeRecipientRes {
+                    address: recipient.address.to_string(),
+                    label: recipient.label.clone(),
+                    bps: recipient.bps,
+                    pending: vec![],
+                    settled: vec![],
+                },
+            );
+        }
+
+        for item in FEES.range(deps.storage, None, None, Order::Ascending) {
+            let ((address, denom), fee) = item?;
+            let res = balances
+                .entry(address.clone())
+                .or_insert_with(|| FeeRecipientRes {
+                    address: address.to_string(),
+                    label: String::new(),
+                    bps: 0,
+                    pending: vec![],
+                    settled: vec![],
+                });
+            res.pending.push(coin(fee.pending, &denom));
+            res.settled.push(coin(fee.settled, &denom));
+        }
+
+        let mut res: Vec<FeeRecipientRes> = recipients
+            .iter()
+            .filter_map(|recipient| balances.remove(&recipient.address))
+            .collect();
+        res.extend(balances.into_values());
+
+        Ok(res)
+    }
+
+    pub fn receive_amount(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        pay_amount: u128,
+        denom: Option<String>,
+        address: Option<String>,
+    ) -> StdResult<u128> {
         let sale_config = SALE_CONFIG.load(deps.storage)?;
+        let denom = denom.unwrap_or_else(|| sale_config.pay_denom.clone());
+        let price = sale_config
+            .price(&denom)
+            .ok_or_else(|| StdError::generic_err(format!("Denom {} is not accepted", denom)))?;
+        let user_price = match address {
+            Some(address) => {
+                let addr = deps.api.addr_validate(&address)?;
+                USER_LIST
+                    .may_load(deps.storage, &addr)?
+                    .and_then(|user| user.price)
+            }
+            None => None,
+        };
+
+        quote(
+            deps,
+            env,
+            &sale_config,
+            &price,
+            pay_amount,
+            user_price.as_ref(),
+        )
+        .map(|quote| quote.tokens)
+        .map_err(|err| StdError::generic_err(err.to_string()))
+    }
+
+    // Value of a payment in pay denom units, the tokens it buys and the rounding dust
+    pub fn quote(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        sale: &SaleConfig,
+        price: &PayDenom,
+        amount: u128,
+        user_price: Option<&PriceOverride>,
+    ) -> Result<Quote, ContractError> {
+        let value = match &price.oracle {
+            Some(config) => {
+                let usd_price = oracle::usd_price(&deps.querier, env.block.time, config)?;
+                oracle::usd_value(usd_price, amount, price.decimals, sale.pay_decimals)?
+            }
+            None => sale.normalize(price, amount)?,
+        };
+        let (tokens, dust) = sale.tokens_for(value, user_price)?;
+
+        Ok(Quote {
+            value,
+            tokens,
+            dust,
+        })
+    }
 
-        Ok(pay_amount * sale_config.price_receive_amount / sale_config.price_pay_amount)
+    // Raised funds per pay denom
+    pub fn raised(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<Vec<RaisedRes>> {
+        let sale_config = SALE_CONFIG.load(deps.storage)?;
+        let denoms = std::iter::once(sale_config.pay_denom.clone()).chain(
+            sale_config
+                .extra_pay_denoms
+                .iter()
+                .map(|pay_denom| pay_denom.denom.clone()),
+        );
+
+        denoms
+            .map(|denom| {
+                let raised = RAISED.may_load(deps.storage, &denom)?.unwrap_or_default();
+                Ok(RaisedRes {
+                    withdrawable: withdrawable(deps, env, &denom)?,
+                    denom,
+                    total: raised.total,
+                    withdrawn: raised.withdrawn,
+                    refunded: raised.refunded,
+                })
+            })
+            .collect()
+    }
+
+    pub fn roles(deps: Deps<InjectiveQueryWrapper>) -> StdResult<GetRolesResponse> {
+        let state = STATE.load(deps.storage)?;
+        let roles = ROLES.load(deps.storage)?;
+        Ok(GetRolesResponse {
+            owner: state.owner,
+            pending_owner: PENDING_OWNER.may_load(deps.storage)?,
+            operator: roles.operator,
+            treasury: roles.treasury,
+            pauser: roles.pauser,
+        })
     }
 
-    pub fn sale(deps: Deps) -> StdResult<GetSaleResponse> {
+    pub fn sale(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<GetSaleResponse> {
         let sale_config = SALE_CONFIG.load(deps.storage)?;
         let sale_data = SALE_DATA.load(deps.storage)?;
         Ok(GetSaleResponse {
+            started: sale_config.is_started(&env.block),
+            ended: sale_config.is_ended(&env.block),
+            unallocated: sale_config
+                .max_supply
+                .saturating_sub(sale_data.total_allocated),
             sale_config,
             sale_data,
         })
     }
 
-    pub fn user(deps: Deps, address: String) -> StdResult<UserInfoRes> {
+    pub fn status(deps: Deps<InjectiveQueryWrapper>, env: &Env) -> StdResult<StatusRes> {
+        let sale = SALE_CONFIG.load(deps.storage)?;
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let block = &env.block;
+
+        let (phase, remaining) = if CANCELLATION.exists(deps.storage) {
+            (SalePhase::Cancelled, None)
+        } else if sale_data.finalized_at.is_some() {
+            (SalePhase::Finalized, None)
+        } else if sale.is_ended(block) {
+            (SalePhase::Ended, None)
+        } else if sale.is_started(block) {
+            let remaining = match sale.end {
+                Expiration::AtHeight(height) => Some(Duration::Height(height - block.height)),
+                Expiration::AtTime(time) => {
+                    Some(Duration::Time(time.seconds() - block.time.seconds()))
+                }
+                Expiration::Never {} => None,
+            };
+            (SalePhase::Active, remaining)
+        } else {
+            let remaining = match sale.start {
+                Scheduled::AtHeight(height) => Duration::Height(height - block.height),
+                Scheduled::AtTime(time) => Duration::Time(time.seconds() - block.time.seconds()),
+            };
+            (SalePhase::Upcoming, Some(remaining))
+        };
+
+        Ok(StatusRes {
+            phase,
+            paused: STATE.load(deps.storage)?.pause_reason.is_some(),
+            remaining,
+            percent_sold: Decimal::checked_from_ratio(sale_data.total_spent * 100, sale.max_supply)
+                .unwrap_or_default(),
+            remaining_supply: sale.max_supply.saturating_sub(sale_data.total_spent),
+            sold_out: sale_data.sold_out_at.is_some(),
+        })
+    }
+
+    // What a plain buy of `amount` by `address` would get, or why it would fail
+    pub fn simulate_buy(
+        deps: Deps<InjectiveQueryWrapper>,
+        env: &Env,
+        address: String,
+        amount: Coin,
+    ) -> StdResult<SimulateBuyRes> {
+        let info = MessageInfo {
+            sender: deps.api.addr_validate(&address)?,
+            funds: vec![amount],
+        };
+        let options = execute::BuyOptions {
+            referrer: None,
+            partial_fill: false,
+            min_tokens_out: None,
+            beneficiary: None,
+            token_id: None,
+        };
+
+        Ok(match execute::plan_buy(deps, env, &info, options) {
+            Ok(plan) => SimulateBuyRes {
+                tokens: Some(plan.quote.tokens),
+                error: None,
+            },
+            Err(err) => SimulateBuyRes {
+                tokens: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+
+    pub fn user(deps: Deps<InjectiveQueryWrapper>, address: String) -> StdResult<UserInfoRes> {
         let user = USER_LIST
             .load(deps.storage, &Addr::unchecked(address.clone()))
-            .unwrap_or(UserInfo {
-                allocation: 0,
-                spent: 0,
-                received_amount: 0,
-            });
+            .unwrap_or_default();
 
         Ok(UserInfoRes {
             address,
             allocation: user.allocation,
             spent: user.spent,
             received_amount: user.received_amount,
+            price: user.price,
+            bought: user.bought,
         })
     }
 
-    pub fn users(deps: Deps) -> StdResult<Vec<UserInfoRes>> {
+    pub fn users(deps: Deps<InjectiveQueryWrapper>) -> StdResult<Vec<UserInfoRes>> {
         let users: Vec<UserInfoRes> = USER_LIST
             .range(deps.storage, None, None, Order::Ascending)
             .map(|item| {
@@ -281,10 +2815,69 @@ pub mod query {
                     allocation: v.allocation,
                     spent: v.spent,
                     received_amount: v.received_amount,
+                    price: v.price,
+                    bought: v.bought,
                 }
             })
             .collect();
 
         Ok(users)
     }
+
+    pub fn buyer_stats(deps: Deps<InjectiveQueryWrapper>) -> StdResult<BuyerStatsRes> {
+        // every buy adds to both the buyer's `bought` and `total_spent`
+        let sale_data = SALE_DATA.load(deps.storage)?;
+        let n = sale_data.contributors;
+        if n == 0 {
+            return Ok(BuyerStatsRes {
+                participants: 0,
+                buys: sale_data.buys,
+                average_contribution: 0,
+                median_contribution: 0,
+            });
+        }
+
+        // the bought index is in order, so the median sits at the middle ranks
+        let middle = USER_LIST
+            .idx
+            .bought
+            .range(deps.storage, None, None, Order::Descending)
+            .skip(((n - 1) / 2) as usize)
+            .take(2 - (n % 2) as usize)
+            .map(|item| item.map(|(_, user)| user.bought))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(BuyerStatsRes {
+            participants: n,
+            buys: sale_data.buys,
+            average_contribution: sale_data.total_spent / n as u128,
+            median_contribution: middle.iter().sum::<u128>() / middle.len() as u128,
+        })
+    }
+
+    pub fn top_buyers(
+        deps: Deps<InjectiveQueryWrapper>,
+        limit: Option<u32>,
+    ) -> StdResult<Vec<UserInfoRes>> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+        USER_LIST
+            .idx
+            .bought
+            .range(deps.storage, None, None, Order::Descending)
+            .take_while(|item| item.as_ref().map_or(true, |(_, user)| user.bought > 0))
+            .take(limit)
+            .map(|item| {
+                let (address, user) = item?;
+                Ok(UserInfoRes {
+                    address: address.to_string(),
+                    allocation: user.allocation,
+                    spent: user.spent,
+                    received_amount: user.received_amount,
+                    price: user.price,
+                    bought: user.bought,
+                })
+            })
+            .collect()
+    }
 }
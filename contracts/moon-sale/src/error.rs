@@ -1,4 +1,5 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Decimal256, StdError};
+use cw_utils::Expiration;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -9,6 +10,18 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("No pending ownership transfer")]
+    NoPendingOwner {},
+
+    #[error("Ownership transfer expired")]
+    OwnershipTransferExpired {},
+
+    #[error("Sale is paused: {reason}")]
+    Paused { reason: String },
+
+    #[error("Sale is not paused")]
+    NotPaused {},
+
     #[error("Sale not active")]
     SaleNotActive {},
 
@@ -23,4 +36,206 @@ pub enum ContractError {
 
     #[error("Sale allocation exceeded")]
     SaleAllocationExceeded { wanted: u128, max: u128 },
+
+    #[error("Allocations of {allocated} exceed the max supply of {max}")]
+    AllocationExceedsSupply { allocated: u128, max: u128 },
+
+    #[error("Price cannot change once the sale has started")]
+    PriceLocked {},
+
+    #[error("Pay denom cannot change once the sale has started")]
+    PayDenomLocked {},
+
+    #[error("Cap unit cannot change after the sale started")]
+    CapUnitLocked {},
+
+    #[error("Sale price amounts must be non-zero")]
+    InvalidPrice {},
+
+    #[error("Decimals must be at most {max}")]
+    InvalidDecimals { max: u32 },
+
+    #[error("Invalid or duplicate pay denom {denom}")]
+    InvalidPayDenom { denom: String },
+
+    #[error("Denom {denom} is not accepted")]
+    UnsupportedDenom { denom: String },
+
+    #[error("No oracle price for {price_id}")]
+    OraclePriceUnavailable { price_id: String },
+
+    #[error("Oracle price for {price_id} is {age}s old")]
+    StaleOraclePrice { price_id: String, age: u64 },
+
+    #[error("Oracle price {price} deviates too far from ema price {ema_price}")]
+    OraclePriceDeviation {
+        price: Decimal256,
+        ema_price: Decimal256,
+    },
+
+    #[error("Buy would return {tokens_out} tokens, below the minimum of {min_tokens_out}")]
+    TokensOutBelowMinimum {
+        tokens_out: u128,
+        min_tokens_out: u128,
+    },
+
+    #[error("Sale cannot end before it starts")]
+    InvalidSchedule {},
+
+    #[error("Anti-sniping needs a timed end before its max end")]
+    InvalidAntiSniping {},
+
+    #[error("Sender can't buy for someone else")]
+    BeneficiaryNotAllowed {},
+
+    #[error("FCFS multiplier must be at least 1")]
+    InvalidFcfsMultiplier {},
+
+    #[error("Invalid price override for {address}")]
+    InvalidPriceOverride { address: String },
+
+    #[error("Buy of {wanted} exceeds the per transaction max of {max}")]
+    MaxPerTxExceeded { wanted: u128, max: u128 },
+
+    #[error("Address is cooling down until {next_buy}")]
+    CooldownActive { next_buy: Expiration },
+
+    #[error("Buy of {wanted} exceeds what is left in this block: {available}")]
+    BlockCapExceeded { wanted: u128, available: u128 },
+
+    #[error("Address reached the max of {max} buys")]
+    MaxBuysReached { max: u64 },
+
+    #[error("Max supply is below total spent")]
+    MaxSupplyBelowSpent { max_supply: u128, total_spent: u128 },
+
+    #[error("Fee shares exceed 100%")]
+    InvalidFeeBps { total: u64 },
+
+    #[error("Withdraw exceeds available balance")]
+    WithdrawExceedsAvailable { denom: String, available: u128 },
+
+    #[error("Referral reward exceeds 100%")]
+    InvalidReferralBps { bps: u64 },
+
+    #[error("Can't refer yourself")]
+    SelfReferral {},
+
+    #[error("Referrer is not whitelisted")]
+    InvalidReferrer {},
+
+    #[error("Nothing to claim")]
+    NothingToClaim {},
+
+    #[error("Sale denom cannot change for a token factory sale")]
+    SaleDenomLocked {},
+
+    #[error("Sale has not ended")]
+    SaleNotEnded {},
+
+    #[error("Sale already finalized")]
+    AlreadyFinalized {},
+
+    #[error("Sale not finalized")]
+    NotFinalized {},
+
+    #[error("Sale denom not set")]
+    NoSaleDenom {},
+
+    #[error("Allocation is below spent amount")]
+    AllocationBelowSpent {
+        address: String,
+        allocation: u128,
+        spent: u128,
+    },
+
+    #[error("Allocation of {address} can't go below zero")]
+    NegativeAllocation { address: String },
+
+    #[error("Allocation transfers are disabled")]
+    TransfersDisabled {},
+
+    #[error("Invalid allocation transfer")]
+    InvalidTransfer {},
+
+    #[error("Recipient {address} is not whitelisted")]
+    RecipientNotWhitelisted { address: String },
+
+    #[error("Allocation of {address} has a price override and cannot be transferred")]
+    PriceOverrideTransfer { address: String },
+
+    #[error("Allocation NFTs are not enabled")]
+    NoAllocationNft {},
+
+    #[error("No allocation for NFT {token_id}")]
+    UnknownAllocationNft { token_id: String },
+
+    #[error("NFT {token_id} is not held by the buyer")]
+    NftNotOwned { token_id: String },
+
+    #[error("User {address} has already bought")]
+    UserAlreadyBought { address: String },
+
+    #[error("Sale cancelled: {reason}")]
+    SaleCancelled { reason: String },
+
+    #[error("Sale not cancelled")]
+    NotCancelled {},
+
+    #[error("Nothing to refund")]
+    NothingToRefund {},
+
+    #[error("Insurance shares can't exceed 100% and the unconditional window must fit the window")]
+    InvalidInsuranceConfig {},
+
+    #[error("Sale has no insurance")]
+    NoInsurance {},
+
+    #[error("Insurance window is closed")]
+    InsuranceClosed {},
+
+    #[error("Raise is reserved until the insurance window closes")]
+    InsuranceWindowOpen {},
+
+    #[error("No unclaimed tokens to give back")]
+    NothingToReturn {},
+
+    #[error("Registration must end before the sale starts and split a non-zero allocation")]
+    InvalidRegistrationConfig {},
+
+    #[error("Registration config cannot change once users registered")]
+    RegistrationLocked {},
+
+    #[error("Sale has no registration phase")]
+    NoRegistration {},
+
+    #[error("Registration is not open")]
+    RegistrationNotOpen {},
+
+    #[error("Registration has not closed")]
+    RegistrationNotClosed {},
+
+    #[error("Already registered")]
+    AlreadyRegistered {},
+
+    #[error("Not registered")]
+    NotRegistered {},
+
+    #[error("Registration needs a deposit of {deposit}")]
+    InvalidDeposit { deposit: String },
+
+    #[error("Invalid registration weight")]
+    InvalidWeight {},
+
+    #[error("Registration needs an escrow of at least {required}")]
+    InsufficientHoldings { required: String },
+
+    #[error("Allocations are set by the operator")]
+    ManualAllocation {},
+
+    #[error("Nothing to allocate")]
+    NothingToAllocate {},
+
+    #[error("Deposits are held until the sale ends")]
+    DepositLocked {},
 }
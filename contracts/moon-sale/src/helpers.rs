@@ -1,19 +1,309 @@
-use cw_multi_test::{App, ContractWrapper, Executor, SudoMsg};
+use std::fmt::Debug;
+
+use anyhow::{bail, Result as AnyResult};
+use cw_multi_test::{
+    App, AppBuilder, AppResponse, BankKeeper, BankSudo, ContractWrapper, CosmosRouter,
+    DistributionKeeper, Executor, GovFailingModule, IbcFailingModule, Module, Router, StakeKeeper,
+    Stargate, SudoMsg, WasmKeeper,
+};
+use cw_storage_plus::Map;
+use injective_cosmwasm::{
+    oracle::types::{PriceState, PythPriceState},
+    InjectiveMsg, InjectiveMsgWrapper, InjectiveQuery, InjectiveQueryWrapper, PythPriceResponse,
+};
+use prost::Message;
 use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
     contract::{execute, instantiate, query},
-    msg::QueryMsg,
+    msg::{QueryMsg, TokenFactoryReq},
+    token_factory::{factory_denom, MsgChangeAdmin, MSG_CHANGE_ADMIN},
+};
+use cosmwasm_std::{
+    testing::{MockApi, MockStorage},
+    to_json_binary, Addr, Api, Binary, BlockInfo, Coin, CustomQuery, Decimal256, Deps, DepsMut,
+    Empty, Env, MessageInfo, Querier, Response, StdResult, Storage,
 };
-use cosmwasm_std::{Addr, Coin, StdResult};
 
 use crate::{
-    msg::{ExecuteMsg, InstantiateMsg, UserInfoReq},
-    state::SaleConfig,
+    msg::{ExecuteMsg, FeeRecipientReq, InstantiateMsg, NftAllocationReq, UserInfoReq},
+    state::{
+        AntiBotConfig, FcfsConfig, InsuranceConfig, PriceOverride, ReferralConfig,
+        RegistrationConfig, SaleConfig, TransferConfig,
+    },
     ContractError,
 };
 
+// denom admins of the token factory stand-in
+const DENOM_ADMINS: Map<&str, Addr> = Map::new("tokenfactory_admins");
+// price id -> pyth price of the oracle stand-in
+const PYTH_PRICES: Map<&str, PythPriceState> = Map::new("oracle_pyth_prices");
+
+// Minimal token factory (create denoms, mint as admin, change admin) and pyth oracle
+#[derive(Default)]
+pub struct InjectiveModule;
+
+impl InjectiveModule {
+    pub fn set_pyth_price(
+        storage: &mut dyn Storage,
+        price_id: &str,
+        price: Decimal256,
+        ema_price: Decimal256,
+        publish_time: i64,
+    ) {
+        let state = PythPriceState {
+            price_id: price_id.to_string(),
+            ema_price: ema_price.into(),
+            ema_conf: Decimal256::zero().into(),
+            conf: Decimal256::zero().into(),
+            publish_time,
+            price_state: PriceState {
+                price: price.into(),
+                cumulative_price: Decimal256::zero().into(),
+                timestamp: publish_time,
+            },
+        };
+        PYTH_PRICES.save(storage, price_id, &state).unwrap();
+    }
+
+    pub fn admin(storage: &dyn Storage, denom: &str) -> Option<Addr> {
+        DENOM_ADMINS.may_load(storage, denom).unwrap()
+    }
+
+    fn check_admin(storage: &dyn Storage, denom: &str, sender: &Addr) -> AnyResult<()> {
+        match Self::admin(storage, denom) {
+            Some(admin) if admin == *sender => Ok(()),
+            _ => bail!("{} is not the admin of {}", sender, denom),
+        }
+    }
+}
+
+impl Module for InjectiveModule {
+    type ExecT = InjectiveMsgWrapper;
+    type QueryT = InjectiveQueryWrapper;
+    type SudoT = Empty;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        sender: Addr,
+        msg: Self::ExecT,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        match msg.msg_data {
+            InjectiveMsg::CreateDenom { subdenom, .. } => {
+                let denom = factory_denom(&sender, &subdenom);
+                if Self::admin(storage, &denom).is_some() {
+                    bail!("denom {} already exists", denom);
+                }
+                DENOM_ADMINS.save(storage, &denom, &sender)?;
+                Ok(AppResponse::default())
+            }
+            InjectiveMsg::SetTokenMetadata { denom, .. } => {
+                Self::check_admin(storage, &denom, &sender)?;
+                Ok(AppResponse::default())
+            }
+            InjectiveMsg::Mint {
+                amount, mint_to, ..
+            } => {
+                Self::check_admin(storage, &amount.denom, &sender)?;
+                router.sudo(
+                    api,
+                    storage,
+                    block,
+                    SudoMsg::Bank(BankSudo::Mint {
+                        to_address: mint_to,
+                        amount: vec![amount],
+                    }),
+                )
+            }
+            msg => bail!("unsupported injective message: {:?}", msg),
+        }
+    }
+
+    fn query(
+        &self,
+        _api: &dyn Api,
+        storage: &dyn Storage,
+        _querier: &dyn Querier,
+        _block: &BlockInfo,
+        request: Self::QueryT,
+    ) -> AnyResult<Binary> {
+        match request.query_data {
+            InjectiveQuery::PythPrice { price_id } => Ok(to_json_binary(&PythPriceResponse {
+                price_state: PYTH_PRICES.may_load(storage, &price_id)?,
+            })?),
+            query => bail!("unsupported injective query: {:?}", query),
+        }
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        _storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        msg: Self::SudoT,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        bail!("unsupported sudo: {:?}", msg)
+    }
+}
+
+impl Stargate for InjectiveModule {
+    fn execute<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        sender: Addr,
+        type_url: String,
+        value: Binary,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        if type_url != MSG_CHANGE_ADMIN {
+            bail!("unsupported stargate message: {}", type_url);
+        }
+        let msg = MsgChangeAdmin::decode(value.as_slice())?;
+        Self::check_admin(storage, &msg.denom, &sender)?;
+        DENOM_ADMINS.save(storage, &msg.denom, &Addr::unchecked(msg.new_admin))?;
+        Ok(AppResponse::default())
+    }
+}
+
+pub type MoonRouter = Router<
+    BankKeeper,
+    InjectiveModule,
+    WasmKeeper<InjectiveMsgWrapper, InjectiveQueryWrapper>,
+    StakeKeeper,
+    DistributionKeeper,
+    IbcFailingModule,
+    GovFailingModule,
+    InjectiveModule,
+>;
+
+pub type MoonApp = App<
+    BankKeeper,
+    MockApi,
+    MockStorage,
+    InjectiveModule,
+    WasmKeeper<InjectiveMsgWrapper, InjectiveQueryWrapper>,
+    StakeKeeper,
+    DistributionKeeper,
+    IbcFailingModule,
+    GovFailingModule,
+    InjectiveModule,
+>;
+
+pub fn mock_app<F>(init_fn: F) -> MoonApp
+where
+    F: FnOnce(&mut MoonRouter, &dyn Api, &mut dyn Storage),
+{
+    AppBuilder::new_custom()
+        .with_custom(InjectiveModule)
+        .with_stargate(InjectiveModule)
+        .build(init_fn)
+}
+
+// cw721-base collection for allocation NFTs, run on the injective app
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AllocationNft(pub Addr);
+
+impl AllocationNft {
+    pub fn addr(&self) -> Addr {
+        self.0.clone()
+    }
+
+    #[track_caller]
+    pub fn instantiate(app: &mut MoonApp, minter: &Addr) -> StdResult<Self> {
+        fn instantiate(
+            deps: DepsMut<InjectiveQueryWrapper>,
+            env: Env,
+            info: MessageInfo,
+            msg: cw721_base::InstantiateMsg,
+        ) -> StdResult<Response<InjectiveMsgWrapper>> {
+            cw721_base::entry::instantiate(deps.into_empty(), env, info, msg)
+                .map(|res| Response::new().add_attributes(res.attributes))
+        }
+        fn execute(
+            deps: DepsMut<InjectiveQueryWrapper>,
+            env: Env,
+            info: MessageInfo,
+            msg: cw721_base::ExecuteMsg<cw721_base::Extension, Empty>,
+        ) -> Result<Response<InjectiveMsgWrapper>, cw721_base::ContractError> {
+            cw721_base::entry::execute(deps.into_empty(), env, info, msg)
+                .map(|res| Response::new().add_attributes(res.attributes))
+        }
+        fn query(
+            deps: Deps<InjectiveQueryWrapper>,
+            env: Env,
+            msg: cw721_base::QueryMsg<Empty>,
+        ) -> StdResult<Binary> {
+            cw721_base::entry::query(deps.into_empty(), env, msg)
+        }
+
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        app.instantiate_contract(
+            code_id,
+            minter.clone(),
+            &cw721_base::InstantiateMsg {
+                name: "Allocations".to_string(),
+                symbol: "ALLOC".to_string(),
+                minter: minter.to_string(),
+            },
+            &[],
+            "Allocations",
+            None,
+        )
+        .map(Self)
+        .map_err(|err| err.downcast().unwrap())
+    }
+
+    #[track_caller]
+    pub fn mint(&self, app: &mut MoonApp, minter: &Addr, token_id: &str, owner: &Addr) {
+        app.execute_contract(
+            minter.clone(),
+            self.0.clone(),
+            &cw721_base::ExecuteMsg::<cw721_base::Extension, Empty>::Mint {
+                token_id: token_id.to_string(),
+                owner: owner.to_string(),
+                token_uri: None,
+                extension: None,
+            },
+            &[],
+        )
+        .unwrap();
+    }
+
+    #[track_caller]
+    pub fn transfer(&self, app: &mut MoonApp, sender: &Addr, recipient: &Addr, token_id: &str) {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &cw721_base::ExecuteMsg::<cw721_base::Extension, Empty>::TransferNft {
+                recipient: recipient.to_string(),
+                token_id: token_id.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct MoonSaleContract(pub Addr);
 
@@ -24,7 +314,7 @@ impl MoonSaleContract {
 
     // General
     #[track_caller]
-    pub fn query_value<T>(&self, app: &App, msg: &QueryMsg) -> StdResult<T>
+    pub fn query_value<T>(&self, app: &MoonApp, msg: &QueryMsg) -> StdResult<T>
     where
         T: DeserializeOwned,
     {
@@ -32,8 +322,8 @@ impl MoonSaleContract {
     }
 
     #[track_caller]
-    pub fn mint_coins(app: &mut App, to: &Addr, funds: Vec<Coin>) {
-        app.sudo(SudoMsg::Bank(cw_multi_test::BankSudo::Mint {
+    pub fn mint_coins(app: &mut MoonApp, to: &Addr, funds: Vec<Coin>) {
+        app.sudo(SudoMsg::Bank(BankSudo::Mint {
             to_address: to.to_string(),
             amount: funds,
         }))
@@ -42,14 +332,14 @@ impl MoonSaleContract {
 
     // Contract functions
     #[track_caller]
-    pub fn store_code(app: &mut App) -> u64 {
+    pub fn store_code(app: &mut MoonApp) -> u64 {
         let contract = ContractWrapper::new(execute, instantiate, query);
         app.store_code(Box::new(contract))
     }
 
     #[track_caller]
     pub fn instantiate(
-        app: &mut App,
+        app: &mut MoonApp,
         code_id: u64,
         sender: &Addr,
         label: &str,
@@ -58,7 +348,10 @@ impl MoonSaleContract {
         app.instantiate_contract(
             code_id,
             sender.clone(),
-            &InstantiateMsg { sale: sale_config },
+            &InstantiateMsg {
+                sale: sale_config,
+                token_factory: None,
+            },
             &[],
             label,
             None,
@@ -68,27 +361,158 @@ impl MoonSaleContract {
     }
 
     #[track_caller]
-    pub fn buy(&self, app: &mut App, sender: &Addr, funds: Vec<Coin>) -> Result<(), ContractError> {
-        app.execute_contract(sender.clone(), self.0.clone(), &ExecuteMsg::Buy {}, &funds)
-            .map_err(|err| err.downcast().unwrap())
-            .map(|_| ())
+    pub fn buy(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Buy {
+                referrer: None,
+                partial_fill: None,
+                min_tokens_out: None,
+                beneficiary: None,
+                token_id: None,
+            },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
     }
 
     #[track_caller]
-    pub fn withdraw(
+    pub fn buy_partial(
         &self,
-        app: &mut App,
+        app: &mut MoonApp,
         sender: &Addr,
-        to: &Addr,
         funds: Vec<Coin>,
     ) -> Result<(), ContractError> {
         app.execute_contract(
             sender.clone(),
             self.0.clone(),
-            &ExecuteMsg::Withdraw {
-                to: to.to_string(),
-                funds,
+            &ExecuteMsg::Buy {
+                referrer: None,
+                partial_fill: Some(true),
+                min_tokens_out: None,
+                beneficiary: None,
+                token_id: None,
             },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn buy_with_min_tokens_out(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        min_tokens_out: u128,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Buy {
+                referrer: None,
+                partial_fill: None,
+                min_tokens_out: Some(min_tokens_out),
+                beneficiary: None,
+                token_id: None,
+            },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn buy_for(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        beneficiary: &Addr,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Buy {
+                referrer: None,
+                partial_fill: None,
+                min_tokens_out: None,
+                beneficiary: Some(beneficiary.to_string()),
+                token_id: None,
+            },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn buy_with_nft(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        token_id: &str,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Buy {
+                referrer: None,
+                partial_fill: None,
+                min_tokens_out: None,
+                beneficiary: None,
+                token_id: Some(token_id.to_string()),
+            },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn buy_with_referrer(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        referrer: &Addr,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Buy {
+                referrer: Some(referrer.to_string()),
+                partial_fill: None,
+                min_tokens_out: None,
+                beneficiary: None,
+                token_id: None,
+            },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn withdraw(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Withdraw { funds },
             &[],
         )
         .map_err(|err| {
@@ -104,7 +528,7 @@ impl MoonSaleContract {
     #[track_caller]
     pub fn update_sale(
         &self,
-        app: &mut App,
+        app: &mut MoonApp,
         sender: &Addr,
         sale_config: SaleConfig,
     ) -> Result<(), ContractError> {
@@ -118,10 +542,31 @@ impl MoonSaleContract {
         .map(|_| ())
     }
 
+    #[track_caller]
+    pub fn set_price_override(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        address: &Addr,
+        price: Option<PriceOverride>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::SetPriceOverride {
+                address: address.to_string(),
+                price,
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
     #[track_caller]
     pub fn set_user_list(
         &self,
-        app: &mut App,
+        app: &mut MoonApp,
         sender: &Addr,
         user_list: Vec<UserInfoReq>,
     ) -> Result<(), ContractError> {
@@ -134,4 +579,443 @@ impl MoonSaleContract {
         .map_err(|err| err.downcast().unwrap())
         .map(|_| ())
     }
+
+    #[track_caller]
+    pub fn update_roles(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        operator: Option<&Addr>,
+        treasury: Option<&Addr>,
+        pauser: Option<&Addr>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateRoles {
+                operator: operator.map(|addr| addr.to_string()),
+                treasury: treasury.map(|addr| addr.to_string()),
+                pauser: pauser.map(|addr| addr.to_string()),
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn update_fee_recipients(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        recipients: Vec<FeeRecipientReq>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateFeeRecipients { recipients },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn settle(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(sender.clone(), self.0.clone(), &ExecuteMsg::Settle {}, &[])
+            .map_err(|err| err.downcast().unwrap())
+            .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn update_referral_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: ReferralConfig,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateReferralConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn claim_referral_rewards(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::ClaimReferralRewards {},
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn instantiate_with_token_factory(
+        app: &mut MoonApp,
+        code_id: u64,
+        sender: &Addr,
+        label: &str,
+        sale_config: SaleConfig,
+        token_factory: TokenFactoryReq,
+    ) -> StdResult<Self> {
+        app.instantiate_contract(
+            code_id,
+            sender.clone(),
+            &InstantiateMsg {
+                sale: sale_config,
+                token_factory: Some(token_factory),
+            },
+            &[],
+            label,
+            None,
+        )
+        .map(MoonSaleContract)
+        .map_err(|err| err.downcast().unwrap())
+    }
+
+    #[track_caller]
+    pub fn finalize(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Finalize {},
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn claim(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(sender.clone(), self.0.clone(), &ExecuteMsg::Claim {}, &[])
+            .map_err(|err| err.downcast().unwrap())
+            .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn update_beneficiary_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        approved_only: bool,
+        approved_senders: Vec<&Addr>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateBeneficiaryConfig {
+                approved_only,
+                approved_senders: approved_senders
+                    .iter()
+                    .map(|addr| addr.to_string())
+                    .collect(),
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    pub fn update_anti_bot_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: AntiBotConfig,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateAntiBotConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    pub fn update_fcfs_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: Option<FcfsConfig>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateFcfsConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn remove_users(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        addresses: Vec<&Addr>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::RemoveUsers {
+                addresses: addresses.iter().map(|addr| addr.to_string()).collect(),
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn adjust_allocations(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        address: &Addr,
+        delta: i128,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::AdjustAllocations {
+                address: address.to_string(),
+                delta,
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn transfer_allocation(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        to: &Addr,
+        amount: u128,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::TransferAllocation {
+                to: to.to_string(),
+                amount,
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    pub fn update_transfer_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: TransferConfig,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateTransferConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    pub fn update_allocation_nft(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        contract: Option<&Addr>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateAllocationNft {
+                contract: contract.map(|addr| addr.to_string()),
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn set_nft_allocations(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        allocations: Vec<NftAllocationReq>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::SetNftAllocations { allocations },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn cancel_sale(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        reason: &str,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::CancelSale {
+                reason: reason.to_string(),
+            },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn refund(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(sender.clone(), self.0.clone(), &ExecuteMsg::Refund {}, &[])
+            .map_err(|err| err.downcast().unwrap())
+            .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn push_refunds(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        limit: Option<u32>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::PushRefunds { limit },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn update_insurance_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: Option<InsuranceConfig>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateInsuranceConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn claim_insurance(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::ClaimInsurance {},
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn update_registration_config(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        config: Option<RegistrationConfig>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::UpdateRegistrationConfig { config },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn register(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        weight: Option<u128>,
+        funds: Vec<Coin>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Register { weight },
+            &funds,
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn unregister(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::Unregister {},
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn allocate_registrations(
+        &self,
+        app: &mut MoonApp,
+        sender: &Addr,
+        limit: Option<u32>,
+    ) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::AllocateRegistrations { limit },
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
+
+    #[track_caller]
+    pub fn return_deposit(&self, app: &mut MoonApp, sender: &Addr) -> Result<(), ContractError> {
+        app.execute_contract(
+            sender.clone(),
+            self.0.clone(),
+            &ExecuteMsg::ReturnDeposit {},
+            &[],
+        )
+        .map_err(|err| err.downcast().unwrap())
+        .map(|_| ())
+    }
 }
@@ -1,10 +1,20 @@
-use cosmwasm_std::{coin, coins, Addr, BlockInfo, Timestamp};
-use cw_multi_test::App;
+use cosmwasm_std::{coin, coins, Addr, BlockInfo, Coin, Decimal, Decimal256, Timestamp};
+use cw_multi_test::{no_init, Executor};
+use cw_utils::{Duration, Expiration, Scheduled};
 
 use crate::{
-    helpers::MoonSaleContract,
-    msg::{GetSaleResponse, QueryMsg, UserInfoReq, UserInfoRes},
-    state::SaleConfig,
+    helpers::{mock_app, AllocationNft, InjectiveModule, MoonApp, MoonSaleContract},
+    msg::{
+        BuyerStatsRes, ClaimRes, ExecuteMsg, FcfsRes, FeeRecipientReq, FeeRecipientRes,
+        GetSaleResponse, InsuranceRes, NftAllocationReq, NftAllocationRes, QueryMsg, RaisedRes,
+        ReferrerRes, RefundStatusRes, RegistrantRes, RegistrationRes, SalePhase, SimulateBuyRes,
+        StatusRes, TokenFactoryReq, UserInfoReq, UserInfoRes, UserLimitsRes,
+    },
+    state::{
+        AllocationRule, AntiBotConfig, AntiSnipingConfig, Cancellation, CapUnit, FcfsConfig,
+        InsuranceConfig, OracleConfig, PayDenom, PriceOverride, ReferralConfig,
+        ReferralRewardDenom, RegistrationConfig, SaleConfig, TransferConfig,
+    },
     ContractError,
 };
 
@@ -14,16 +24,22 @@ pub fn buy_success() {
     let owner = Addr::unchecked("owner");
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
-    let mut app = App::new(|router, _, storage| {
+    let mut app = mock_app(|router, _, storage| {
         router
             .bank
             .init_balance(storage, &user, coins(1000, "uusd"))
@@ -44,6 +60,7 @@ pub fn buy_success() {
         address: user.to_string(),
         allocation: 100,
         spent: 0,
+        price: None,
     }];
     contract
         .set_user_list(&mut app, &owner, users.clone())
@@ -69,6 +86,8 @@ pub fn buy_success() {
             allocation: 100,
             spent: 3,
             received_amount: 3,
+            price: None,
+            bought: 3,
         },
         user_res
     );
@@ -80,16 +99,22 @@ pub fn buy_without_sale_available_allocation() {
     let owner = Addr::unchecked("owner");
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
     };
 
-    let mut app = App::new(|router, _, storage| {
+    let mut app = mock_app(|router, _, storage| {
         router
             .bank
             .init_balance(storage, &user, coins(1000, "uusd"))
@@ -110,6 +135,7 @@ pub fn buy_without_sale_available_allocation() {
         address: user.to_string(),
         allocation: 1000,
         spent: 0,
+        price: None,
     }];
     contract
         .set_user_list(&mut app, &owner, users.clone())
@@ -132,16 +158,22 @@ pub fn buy_without_user_available_allocation() {
     let owner = Addr::unchecked("owner");
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
     };
 
-    let mut app = App::new(|router, _, storage| {
+    let mut app = mock_app(|router, _, storage| {
         router
             .bank
             .init_balance(storage, &user, coins(1000, "uusd"))
@@ -162,6 +194,7 @@ pub fn buy_without_user_available_allocation() {
         address: user.to_string(),
         allocation: 100,
         spent: 0,
+        price: None,
     }];
     contract
         .set_user_list(&mut app, &owner, users.clone())
@@ -183,16 +216,22 @@ pub fn withdraw_success() {
     let owner = Addr::unchecked("owner");
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
-    let mut app = App::default();
+    let mut app = mock_app(no_init);
     let code_id = MoonSaleContract::store_code(&mut app);
 
     let contract =
@@ -201,7 +240,15 @@ pub fn withdraw_success() {
     MoonSaleContract::mint_coins(&mut app, &contract.addr(), coins(100, "uusd"));
 
     contract
-        .withdraw(&mut app, &owner, &user, coins(100, "uusd"))
+        .update_roles(&mut app, &owner, None, Some(&user), None)
+        .unwrap();
+
+    // only owner
+    let res = contract.withdraw(&mut app, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+
+    contract
+        .withdraw(&mut app, &owner, coins(100, "uusd"))
         .unwrap();
 
     let res = app.wrap().query_balance(user, "uusd").unwrap();
@@ -217,16 +264,22 @@ pub fn withdraw_fail_insufficient_balance() {
     let owner = Addr::unchecked("owner");
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
-    let mut app = App::default();
+    let mut app = mock_app(no_init);
     let code_id = MoonSaleContract::store_code(&mut app);
 
     let contract =
@@ -234,7 +287,3540 @@ pub fn withdraw_fail_insufficient_balance() {
 
     MoonSaleContract::mint_coins(&mut app, &contract.addr(), coins(100, "uusd"));
 
-    let res = contract.withdraw(&mut app, &owner, &user, coins(200, "uusd"));
+    contract
+        .update_roles(&mut app, &owner, None, Some(&user), None)
+        .unwrap();
+
+    let res = contract.withdraw(&mut app, &owner, coins(200, "uusd"));
 
     assert!(res.is_err());
 }
+
+#[test]
+pub fn fee_split_and_settle() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+    let launchpad = Addr::unchecked("launchpad");
+    let liquidity = Addr::unchecked("liquidity");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // shares can't exceed 100%
+    let res = contract.update_fee_recipients(
+        &mut app,
+        &owner,
+        vec![
+            FeeRecipientReq {
+                address: launchpad.to_string(),
+                label: "launchpad".to_string(),
+                bps: 6000,
+            },
+            FeeRecipientReq {
+                address: liquidity.to_string(),
+                label: "liquidity".to_string(),
+                bps: 5000,
+            },
+        ],
+    );
+    assert_eq!(Err(ContractError::InvalidFeeBps { total: 11000 }), res);
+
+    contract
+        .update_fee_recipients(
+            &mut app,
+            &owner,
+            vec![
+                FeeRecipientReq {
+                    address: launchpad.to_string(),
+                    label: "launchpad".to_string(),
+                    bps: 500,
+                },
+                FeeRecipientReq {
+                    address: liquidity.to_string(),
+                    label: "liquidity".to_string(),
+                    bps: 2000,
+                },
+            ],
+        )
+        .unwrap();
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    contract.buy(&mut app, &user, coins(1000, "uusd")).unwrap();
+
+    let fees: Vec<FeeRecipientRes> = contract.query_value(&app, &QueryMsg::GetFees {}).unwrap();
+    assert_eq!(
+        vec![
+            FeeRecipientRes {
+                address: launchpad.to_string(),
+                label: "launchpad".to_string(),
+                bps: 500,
+                pending: coins(50, "uusd"),
+                settled: coins(0, "uusd"),
+            },
+            FeeRecipientRes {
+                address: liquidity.to_string(),
+                label: "liquidity".to_string(),
+                bps: 2000,
+                pending: coins(200, "uusd"),
+                settled: coins(0, "uusd"),
+            },
+        ],
+        fees
+    );
+
+    // nothing leaves while the sale can still be cancelled
+    let res = contract.withdraw(&mut app, &owner, coins(750, "uusd"));
+    assert_eq!(Err(ContractError::NotFinalized {}), res);
+    let res = contract.settle(&mut app, &user);
+    assert_eq!(Err(ContractError::NotFinalized {}), res);
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    contract.finalize(&mut app, &owner).unwrap();
+
+    // pending fees are not withdrawable
+    let res = contract.withdraw(&mut app, &owner, coins(751, "uusd"));
+    assert_eq!(
+        Err(ContractError::WithdrawExceedsAvailable {
+            denom: "uusd".to_string(),
+            available: 750
+        }),
+        res
+    );
+    contract
+        .withdraw(&mut app, &owner, coins(750, "uusd"))
+        .unwrap();
+
+    // anyone can settle
+    contract.settle(&mut app, &user).unwrap();
+
+    let res = app.wrap().query_balance(&launchpad, "uusd").unwrap();
+    assert_eq!(res, coin(50, "uusd"));
+    let res = app.wrap().query_balance(&liquidity, "uusd").unwrap();
+    assert_eq!(res, coin(200, "uusd"));
+    let res = app.wrap().query_balance(contract.addr(), "uusd").unwrap();
+    assert_eq!(res, coin(0, "uusd"));
+
+    let fees: Vec<FeeRecipientRes> = contract.query_value(&app, &QueryMsg::GetFees {}).unwrap();
+    assert_eq!(coins(0, "uusd"), fees[0].pending);
+    assert_eq!(coins(50, "uusd"), fees[0].settled);
+    assert_eq!(coins(200, "uusd"), fees[1].settled);
+}
+
+#[test]
+pub fn referral_rewards() {
+    let user = Addr::unchecked("user");
+    let referrer = Addr::unchecked("referrer");
+    let stranger = Addr::unchecked("stranger");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    contract
+        .update_referral_config(
+            &mut app,
+            &owner,
+            ReferralConfig {
+                reward_bps: 1000,
+                reward_denom: ReferralRewardDenom::Pay,
+            },
+        )
+        .unwrap();
+
+    // set users
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: referrer.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // self and unwhitelisted referrals are rejected
+    let res = contract.buy_with_referrer(&mut app, &user, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::SelfReferral {}), res);
+    let res = contract.buy_with_referrer(&mut app, &user, &stranger, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::InvalidReferrer {}), res);
+
+    contract
+        .buy_with_referrer(&mut app, &user, &referrer, coins(500, "uusd"))
+        .unwrap();
+
+    // rewards in sale tokens
+    contract
+        .update_referral_config(
+            &mut app,
+            &owner,
+            ReferralConfig {
+                reward_bps: 500,
+                reward_denom: ReferralRewardDenom::Sale,
+            },
+        )
+        .unwrap();
+    contract
+        .buy_with_referrer(&mut app, &user, &referrer, coins(200, "uusd"))
+        .unwrap();
+
+    let referrer_res: ReferrerRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetReferrer {
+                address: referrer.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        ReferrerRes {
+            address: referrer.to_string(),
+            volume: 700,
+            buys: 2,
+            token_reward: 20,
+            pending: coins(50, "uusd"),
+            claimed: coins(0, "uusd"),
+        },
+        referrer_res
+    );
+
+    let top: Vec<ReferrerRes> = contract
+        .query_value(&app, &QueryMsg::GetTopReferrers { limit: Some(5) })
+        .unwrap();
+    assert_eq!(vec![referrer_res], top);
+
+    // rewards wait for finalization
+    let res = contract.claim_referral_rewards(&mut app, &referrer);
+    assert_eq!(Err(ContractError::NotFinalized {}), res);
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    contract.finalize(&mut app, &owner).unwrap();
+
+    // pending rewards are not withdrawable
+    let res = contract.withdraw(&mut app, &owner, coins(700, "uusd"));
+    assert_eq!(
+        Err(ContractError::WithdrawExceedsAvailable {
+            denom: "uusd".to_string(),
+            available: 650
+        }),
+        res
+    );
+
+    contract
+        .claim_referral_rewards(&mut app, &referrer)
+        .unwrap();
+    let res = app.wrap().query_balance(&referrer, "uusd").unwrap();
+    assert_eq!(res, coin(50, "uusd"));
+
+    let res = contract.claim_referral_rewards(&mut app, &referrer);
+    assert_eq!(Err(ContractError::NothingToClaim {}), res);
+}
+
+#[test]
+pub fn token_factory_finalize_and_claim() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: None,
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract = MoonSaleContract::instantiate_with_token_factory(
+        &mut app,
+        code_id,
+        &owner,
+        "Contract",
+        sale,
+        TokenFactoryReq {
+            subdenom: "moon".to_string(),
+            name: "Moon".to_string(),
+            symbol: "MOON".to_string(),
+            decimals: 6,
+        },
+    )
+    .unwrap();
+    let denom = format!("factory/{}/moon", contract.addr());
+
+    // contract owns the new denom
+    let sale_res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(Some(denom.clone()), sale_res.sale_config.sale_denom);
+    assert_eq!(
+        Some(contract.addr()),
+        app.read_module(|_, _, storage| InjectiveModule::admin(storage, &denom))
+    );
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+    contract.buy(&mut app, &user, coins(300, "uusd")).unwrap();
+
+    // nothing to claim before finalization
+    let res = contract.claim(&mut app, &user);
+    assert_eq!(Err(ContractError::NotFinalized {}), res);
+    let res = contract.finalize(&mut app, &owner);
+    assert_eq!(Err(ContractError::SaleNotEnded {}), res);
+
+    app.set_block(BlockInfo {
+        height: 2,
+        time: Timestamp::from_seconds(10),
+        chain_id: "random-test".to_string(),
+    });
+    let res = contract.finalize(&mut app, &user);
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    contract.finalize(&mut app, &owner).unwrap();
+    let res = contract.finalize(&mut app, &owner);
+    assert_eq!(Err(ContractError::AlreadyFinalized {}), res);
+
+    // the sale can't be reopened
+    let sale_res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            end: Expiration::AtTime(Timestamp::from_seconds(20)),
+            ..sale_res.sale_config
+        },
+    );
+    assert_eq!(Err(ContractError::AlreadyFinalized {}), res);
+
+    // exactly what is owed is minted and the owner takes over the denom
+    let res = app.wrap().query_balance(contract.addr(), &denom).unwrap();
+    assert_eq!(res, coin(600, &denom));
+    assert_eq!(
+        Some(owner.clone()),
+        app.read_module(|_, _, storage| InjectiveModule::admin(storage, &denom))
+    );
+
+    contract.claim(&mut app, &user).unwrap();
+    let res = app.wrap().query_balance(&user, &denom).unwrap();
+    assert_eq!(res, coin(600, &denom));
+
+    let claim_res: ClaimRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetClaim {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        ClaimRes {
+            address: user.to_string(),
+            owed: 600,
+            claimed: 600,
+        },
+        claim_res
+    );
+
+    let res = contract.claim(&mut app, &user);
+    assert_eq!(Err(ContractError::NothingToClaim {}), res);
+}
+
+#[test]
+pub fn buy_with_extra_pay_denoms() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![PayDenom {
+            denom: "uinj".to_string(),
+            price_pay_amount: 1,
+            price_receive_amount: 20,
+            decimals: 0,
+            oracle: None,
+        }],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(
+                storage,
+                &user,
+                vec![coin(1000, "uusd"), coin(1000, "uinj"), coin(1000, "uatom")],
+            )
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // the main price can't be zero
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            price_receive_amount: 0,
+            ..sale.clone()
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidPrice {}), res);
+
+    // decimals past what prices can scale by are refused
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            sale_decimals: 78,
+            ..sale.clone()
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidDecimals { max: 18 }), res);
+
+    // pay denoms can't repeat
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            extra_pay_denoms: vec![PayDenom {
+                denom: "uusd".to_string(),
+                price_pay_amount: 1,
+                price_receive_amount: 2,
+                decimals: 0,
+                oracle: None,
+            }],
+            pay_decimals: 0,
+            sale_decimals: 0,
+            ..sale
+        },
+    );
+    assert_eq!(
+        Err(ContractError::InvalidPayDenom {
+            denom: "uusd".to_string()
+        }),
+        res
+    );
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let res = contract.buy(&mut app, &user, coins(100, "uatom"));
+    assert_eq!(
+        Err(ContractError::UnsupportedDenom {
+            denom: "uatom".to_string()
+        }),
+        res
+    );
+
+    contract.buy(&mut app, &user, coins(300, "uusd")).unwrap();
+    // 50 uinj buys 1000 tokens, worth 500 uusd
+    contract.buy(&mut app, &user, coins(50, "uinj")).unwrap();
+
+    let res = contract.buy(&mut app, &user, coins(30, "uinj"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 300,
+            max: 200
+        }),
+        res
+    );
+
+    let user_res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 800,
+            received_amount: 1600,
+            price: None,
+            bought: 800,
+        },
+        user_res
+    );
+
+    let received: u128 = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetReceivedAmount {
+                pay_amount: 10,
+                denom: Some("uinj".to_string()),
+                address: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(200, received);
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    contract.finalize(&mut app, &owner).unwrap();
+    contract
+        .withdraw(&mut app, &owner, coins(20, "uinj"))
+        .unwrap();
+
+    let raised: Vec<RaisedRes> = contract.query_value(&app, &QueryMsg::GetRaised {}).unwrap();
+    assert_eq!(
+        vec![
+            RaisedRes {
+                denom: "uusd".to_string(),
+                total: 300,
+                withdrawn: 0,
+                withdrawable: 300,
+                refunded: 0,
+            },
+            RaisedRes {
+                denom: "uinj".to_string(),
+                total: 50,
+                withdrawn: 20,
+                withdrawable: 30,
+                refunded: 0,
+            },
+        ],
+        raised
+    );
+}
+
+#[test]
+pub fn buy_with_oracle_price() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+    let one_inj: u128 = 1_000_000_000_000_000_000;
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1_000_000_000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![PayDenom {
+            denom: "inj".to_string(),
+            price_pay_amount: 0,
+            price_receive_amount: 0,
+            decimals: 18,
+            oracle: Some(OracleConfig {
+                price_id: "inj_usd".to_string(),
+                max_staleness: 3,
+                max_deviation_bps: 500,
+            }),
+        }],
+        pay_decimals: 6,
+        sale_decimals: 6,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(10 * one_inj, "inj"))
+            .unwrap();
+        InjectiveModule::set_pyth_price(
+            storage,
+            "inj_usd",
+            Decimal256::percent(2500),
+            Decimal256::percent(2450),
+            5,
+        );
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // oracle prices need the decimals of both denoms
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            pay_decimals: 0,
+            ..sale
+        },
+    );
+    assert_eq!(
+        Err(ContractError::InvalidPayDenom {
+            denom: "inj".to_string()
+        }),
+        res
+    );
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 500_000_000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let received: u128 = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetReceivedAmount {
+                pay_amount: one_inj,
+                denom: Some("inj".to_string()),
+                address: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(50_000_000, received);
+
+    // 2 INJ at 25 USD
+    contract
+        .buy(&mut app, &user, coins(2 * one_inj, "inj"))
+        .unwrap();
+
+    let user_res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(50_000_000, user_res.spent);
+    assert_eq!(100_000_000, user_res.received_amount);
+
+    // spot price too far from the ema price
+    app.init_modules(|_, _, storage| {
+        InjectiveModule::set_pyth_price(
+            storage,
+            "inj_usd",
+            Decimal256::percent(4000),
+            Decimal256::percent(2500),
+            5,
+        )
+    });
+    let res = contract.buy(&mut app, &user, coins(one_inj, "inj"));
+    assert_eq!(
+        Err(ContractError::OraclePriceDeviation {
+            price: Decimal256::percent(4000),
+            ema_price: Decimal256::percent(2500),
+        }),
+        res
+    );
+
+    // stale price
+    app.init_modules(|_, _, storage| {
+        InjectiveModule::set_pyth_price(
+            storage,
+            "inj_usd",
+            Decimal256::percent(2500),
+            Decimal256::percent(2500),
+            5,
+        )
+    });
+    app.set_block(BlockInfo {
+        height: 2,
+        time: Timestamp::from_seconds(9),
+        chain_id: "random-test".to_string(),
+    });
+    let res = contract.buy(&mut app, &user, coins(one_inj, "inj"));
+    assert_eq!(
+        Err(ContractError::StaleOraclePrice {
+            price_id: "inj_usd".to_string(),
+            age: 4,
+        }),
+        res
+    );
+}
+
+#[test]
+pub fn buy_partial_fill() {
+    let user = Addr::unchecked("user");
+    let user2 = Addr::unchecked("user2");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 150,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &user2, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: user2.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // without partial fill oversized buys are still rejected
+    let res = contract.buy(&mut app, &user, coins(150, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 150,
+            max: 100
+        }),
+        res
+    );
+
+    // capped by the user allocation
+    contract
+        .buy_partial(&mut app, &user, coins(150, "uusd"))
+        .unwrap();
+    let res = app.wrap().query_balance(&user, "uusd").unwrap();
+    assert_eq!(res, coin(900, "uusd"));
+
+    // capped by what's left of max supply
+    contract
+        .buy_partial(&mut app, &user2, coins(100, "uusd"))
+        .unwrap();
+    let res = app.wrap().query_balance(&user2, "uusd").unwrap();
+    assert_eq!(res, coin(950, "uusd"));
+
+    let user_res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user2.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: user2.to_string(),
+            allocation: 100,
+            spent: 50,
+            received_amount: 100,
+            price: None,
+            bought: 50,
+        },
+        user_res
+    );
+
+    // sold out, the sale is over
+    let res = contract.buy_partial(&mut app, &user2, coins(10, "uusd"));
+    assert_eq!(Err(ContractError::SaleNotActive {}), res);
+
+    let res = app.wrap().query_balance(contract.addr(), "uusd").unwrap();
+    assert_eq!(res, coin(150, "uusd"));
+}
+
+#[test]
+pub fn buy_with_decimals_and_min_tokens_out() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    // 3 USD per whole token, 6 decimal pay denom and 2 decimal sale token
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1_000_000_000,
+        price_pay_amount: 3,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 6,
+        sale_decimals: 2,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(100_000_000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 100_000_000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // 10 USD buys 3.33 tokens
+    let res = contract.buy_with_min_tokens_out(&mut app, &user, 334, coins(10_000_000, "uusd"));
+    assert_eq!(
+        Err(ContractError::TokensOutBelowMinimum {
+            tokens_out: 333,
+            min_tokens_out: 334,
+        }),
+        res
+    );
+    contract
+        .buy_with_min_tokens_out(&mut app, &user, 333, coins(10_000_000, "uusd"))
+        .unwrap();
+
+    // 0.01 USD was left over by rounding down
+    let sale_res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(10_000_000, sale_res.sale_data.total_spent);
+    assert_eq!(333, sale_res.sale_data.total_tokens_sold);
+    assert_eq!(10_000, sale_res.sale_data.dust);
+}
+
+#[test]
+pub fn buy_with_height_schedule() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtHeight(10),
+        end: Expiration::AtHeight(20),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 5,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // end can't come before start, or never
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            end: Expiration::AtHeight(9),
+            ..sale.clone()
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidSchedule {}), res);
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            end: Expiration::Never {},
+            ..sale
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidSchedule {}), res);
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let res = contract.buy(&mut app, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::SaleNotActive {}), res);
+
+    app.update_block(|block| block.height = 10);
+    let sale_res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert!(sale_res.started && !sale_res.ended);
+    contract.buy(&mut app, &user, coins(100, "uusd")).unwrap();
+    let res = contract.finalize(&mut app, &owner);
+    assert_eq!(Err(ContractError::SaleNotEnded {}), res);
+
+    app.update_block(|block| block.height = 20);
+    let sale_res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert!(sale_res.ended);
+    let res = contract.buy(&mut app, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::SaleNotActive {}), res);
+    contract.finalize(&mut app, &owner).unwrap();
+}
+
+#[test]
+pub fn buy_for_beneficiary() {
+    let user = Addr::unchecked("user");
+    let relayer = Addr::unchecked("relayer");
+    let stranger = Addr::unchecked("stranger");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &relayer, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &stranger, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // nobody is approved by default
+    let res = contract.buy_for(&mut app, &relayer, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::BeneficiaryNotAllowed {}), res);
+
+    let res = contract.update_beneficiary_config(&mut app, &relayer, true, vec![&relayer]);
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    contract
+        .update_beneficiary_config(&mut app, &owner, true, vec![&relayer])
+        .unwrap();
+
+    // the relayer pays, the user's allocation is used
+    contract
+        .buy_for(&mut app, &relayer, &user, coins(100, "uusd"))
+        .unwrap();
+    let res = app.wrap().query_balance(&relayer, "uusd").unwrap();
+    assert_eq!(res, coin(900, "uusd"));
+
+    let user_res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 100,
+            received_amount: 200,
+            price: None,
+            bought: 100,
+        },
+        user_res
+    );
+
+    let res = contract.buy_for(&mut app, &stranger, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::BeneficiaryNotAllowed {}), res);
+
+    // anyone can buy for a whitelisted user once the restriction is lifted
+    contract
+        .update_beneficiary_config(&mut app, &owner, false, vec![])
+        .unwrap();
+    contract
+        .buy_for(&mut app, &stranger, &user, coins(100, "uusd"))
+        .unwrap();
+}
+
+#[test]
+pub fn buy_with_anti_bot_limits() {
+    let user = Addr::unchecked("user");
+    let user2 = Addr::unchecked("user2");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(100)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &user2, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: user2.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let config = AntiBotConfig {
+        max_per_tx: Some(200),
+        cooldown: Some(Duration::Height(2)),
+        max_per_block: Some(300),
+        max_buys: Some(2),
+    };
+    let res = contract.update_anti_bot_config(&mut app, &user, config.clone());
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    contract
+        .update_anti_bot_config(&mut app, &owner, config)
+        .unwrap();
+
+    // per transaction max
+    let res = contract.buy(&mut app, &user, coins(201, "uusd"));
+    assert_eq!(
+        Err(ContractError::MaxPerTxExceeded {
+            wanted: 201,
+            max: 200
+        }),
+        res
+    );
+    contract.buy(&mut app, &user, coins(200, "uusd")).unwrap();
+
+    // cooldown until height 3
+    let res = contract.buy(&mut app, &user, coins(100, "uusd"));
+    assert_eq!(
+        Err(ContractError::CooldownActive {
+            next_buy: Expiration::AtHeight(3)
+        }),
+        res
+    );
+
+    // per block cap across buyers, partial fills take what is left
+    let res = contract.buy(&mut app, &user2, coins(200, "uusd"));
+    assert_eq!(
+        Err(ContractError::BlockCapExceeded {
+            wanted: 200,
+            available: 100
+        }),
+        res
+    );
+    contract
+        .buy_partial(&mut app, &user2, coins(200, "uusd"))
+        .unwrap();
+    let res = app.wrap().query_balance(&user2, "uusd").unwrap();
+    assert_eq!(res, coin(900, "uusd"));
+
+    let res: UserLimitsRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserLimits {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserLimitsRes {
+            address: user.to_string(),
+            available_allocation: 800,
+            max_per_tx: Some(200),
+            block_available: Some(0),
+            buys: 1,
+            max_buys: Some(2),
+            next_buy: Some(Expiration::AtHeight(3)),
+        },
+        res
+    );
+
+    // the cooldown and block cap reset, the buy count doesn't
+    app.update_block(|block| block.height += 2);
+    contract.buy(&mut app, &user, coins(100, "uusd")).unwrap();
+    app.update_block(|block| block.height += 2);
+    let res = contract.buy(&mut app, &user, coins(100, "uusd"));
+    assert_eq!(Err(ContractError::MaxBuysReached { max: 2 }), res);
+
+    let res: UserLimitsRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserLimits {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserLimitsRes {
+            address: user.to_string(),
+            available_allocation: 700,
+            max_per_tx: Some(200),
+            block_available: Some(300),
+            buys: 2,
+            max_buys: Some(2),
+            next_buy: None,
+        },
+        res
+    );
+}
+
+#[test]
+pub fn buy_with_price_override() {
+    let user = Addr::unchecked("user");
+    let investor = Addr::unchecked("investor");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &investor, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(2),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // zero prices are rejected
+    let users = vec![UserInfoReq {
+        address: investor.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: Some(PriceOverride {
+            price_pay_amount: 1,
+            price_receive_amount: 0,
+        }),
+    }];
+    let res = contract.set_user_list(&mut app, &owner, users);
+    assert_eq!(
+        Err(ContractError::InvalidPriceOverride {
+            address: investor.to_string()
+        }),
+        res
+    );
+
+    // the investor gets 5 tokens per uusd instead of 2
+    let price = PriceOverride {
+        price_pay_amount: 2,
+        price_receive_amount: 10,
+    };
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: investor.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: Some(price.clone()),
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // only the owner sets prices, the operator only manages allocations
+    let operator = Addr::unchecked("operator");
+    contract
+        .update_roles(&mut app, &owner, Some(&operator), None, None)
+        .unwrap();
+    let res = contract.set_user_list(
+        &mut app,
+        &operator,
+        vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: Some(price.clone()),
+        }],
+    );
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    let res = contract.set_price_override(&mut app, &operator, &user, Some(price.clone()));
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    let users = vec![UserInfoReq {
+        address: investor.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: Some(price.clone()),
+    }];
+    contract.set_user_list(&mut app, &operator, users).unwrap();
+    contract
+        .set_price_override(&mut app, &owner, &user, Some(price.clone()))
+        .unwrap();
+    contract
+        .set_price_override(&mut app, &owner, &user, None)
+        .unwrap();
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(5));
+
+    // and they lock once the sale starts
+    let res = contract.set_price_override(&mut app, &owner, &investor, None);
+    assert_eq!(Err(ContractError::PriceLocked {}), res);
+
+    let quote = |app: &MoonApp, address: Option<&Addr>| -> u128 {
+        contract
+            .query_value(
+                app,
+                &QueryMsg::GetReceivedAmount {
+                    pay_amount: 100,
+                    denom: None,
+                    address: address.map(|addr| addr.to_string()),
+                },
+            )
+            .unwrap()
+    };
+    assert_eq!(quote(&app, None), 200);
+    assert_eq!(quote(&app, Some(&user)), 200);
+    assert_eq!(quote(&app, Some(&investor)), 500);
+
+    contract.buy(&mut app, &user, coins(100, "uusd")).unwrap();
+    contract
+        .buy(&mut app, &investor, coins(100, "uusd"))
+        .unwrap();
+
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: investor.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: investor.to_string(),
+            allocation: 1000,
+            spent: 100,
+            received_amount: 500,
+            price: Some(price),
+            bought: 100,
+        },
+        res
+    );
+
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(res.sale_data.total_spent, 200);
+    assert_eq!(res.sale_data.total_tokens_sold, 700);
+}
+
+#[test]
+pub fn buy_in_fcfs_phase() {
+    let user = Addr::unchecked("user");
+    let user2 = Addr::unchecked("user2");
+    let user3 = Addr::unchecked("user3");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for addr in [&user, &user2, &user3] {
+            router
+                .bank
+                .init_balance(storage, addr, coins(1000, "uusd"))
+                .unwrap();
+        }
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = [&user, &user2, &user3]
+        .iter()
+        .map(|addr| UserInfoReq {
+            address: addr.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        })
+        .collect();
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let res = contract.update_fcfs_config(
+        &mut app,
+        &owner,
+        Some(FcfsConfig {
+            start: Scheduled::AtTime(Timestamp::from_seconds(8)),
+            multiplier: Decimal::percent(50),
+        }),
+    );
+    assert_eq!(Err(ContractError::InvalidFcfsMultiplier {}), res);
+    contract
+        .update_fcfs_config(
+            &mut app,
+            &owner,
+            Some(FcfsConfig {
+                start: Scheduled::AtTime(Timestamp::from_seconds(8)),
+                multiplier: Decimal::percent(200),
+            }),
+        )
+        .unwrap();
+
+    // guaranteed allocations only until the FCFS phase starts
+    contract.buy(&mut app, &user, coins(100, "uusd")).unwrap();
+    let res = contract.buy(&mut app, &user, coins(50, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded { wanted: 50, max: 0 }),
+        res
+    );
+
+    let res: FcfsRes = contract.query_value(&app, &QueryMsg::GetFcfs {}).unwrap();
+    assert!(!res.active);
+    assert_eq!(res.pool, 200);
+
+    // up to 2x the allocation, drawn from what others left unspent
+    app.update_block(|block| block.time = Timestamp::from_seconds(8));
+    contract.buy(&mut app, &user, coins(100, "uusd")).unwrap();
+    let res = contract.buy(&mut app, &user, coins(1, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded { wanted: 1, max: 0 }),
+        res
+    );
+
+    let res = contract.buy(&mut app, &user2, coins(150, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 150,
+            max: 100
+        }),
+        res
+    );
+    contract.buy(&mut app, &user2, coins(100, "uusd")).unwrap();
+
+    // nothing left for latecomers
+    let res = contract.buy(&mut app, &user3, coins(10, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded { wanted: 10, max: 0 }),
+        res
+    );
+
+    let res: FcfsRes = contract.query_value(&app, &QueryMsg::GetFcfs {}).unwrap();
+    assert!(res.active);
+    assert_eq!(res.pool, 0);
+
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.spent, 200);
+}
+
+#[test]
+pub fn allocations_within_max_supply() {
+    let user = Addr::unchecked("user");
+    let user2 = Addr::unchecked("user2");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 100,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|_, _, _| {});
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(2),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    let user_list = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 60,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: user2.to_string(),
+            allocation: 50,
+            spent: 0,
+            price: None,
+        },
+    ];
+    let res = contract.set_user_list(&mut app, &owner, user_list.clone());
+    assert_eq!(
+        Err(ContractError::AllocationExceedsSupply {
+            allocated: 110,
+            max: 100
+        }),
+        res
+    );
+
+    contract
+        .set_user_list(&mut app, &owner, user_list[..1].to_vec())
+        .unwrap();
+    let res = contract.adjust_allocations(&mut app, &owner, &user, 41);
+    assert_eq!(
+        Err(ContractError::AllocationExceedsSupply {
+            allocated: 101,
+            max: 100
+        }),
+        res
+    );
+    contract
+        .adjust_allocations(&mut app, &owner, &user, 40)
+        .unwrap();
+
+    // oversubscribed sales take allocations past the supply
+    let oversubscribed = SaleConfig {
+        allow_oversubscription: true,
+        ..sale.clone()
+    };
+    contract
+        .update_sale(&mut app, &owner, oversubscribed)
+        .unwrap();
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!((res.sale_data.total_allocated, res.unallocated), (110, 0));
+
+    // and can't go back while they do
+    let res = contract.update_sale(&mut app, &owner, sale);
+    assert_eq!(
+        Err(ContractError::AllocationExceedsSupply {
+            allocated: 110,
+            max: 100
+        }),
+        res
+    );
+}
+
+#[test]
+pub fn remove_users_and_adjust_allocations() {
+    let user = Addr::unchecked("user");
+    let user2 = Addr::unchecked("user2");
+    let user3 = Addr::unchecked("user3");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: user2.to_string(),
+            allocation: 200,
+            spent: 0,
+            price: None,
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(res.sale_data.total_allocated, 300);
+    assert_eq!(res.unallocated, 700);
+
+    contract.buy(&mut app, &user, coins(50, "uusd")).unwrap();
+
+    // only the operator, and never users with buy history
+    let res = contract.remove_users(&mut app, &user, vec![&user2]);
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    let res = contract.remove_users(&mut app, &owner, vec![&user2, &user]);
+    assert_eq!(
+        Err(ContractError::UserAlreadyBought {
+            address: user.to_string()
+        }),
+        res
+    );
+    contract
+        .remove_users(&mut app, &owner, vec![&user2])
+        .unwrap();
+
+    let res: Vec<UserInfoRes> = contract.query_value(&app, &QueryMsg::GetUsers {}).unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].address, user.to_string());
+
+    // deltas keep the buy history
+    let res = contract.adjust_allocations(&mut app, &owner, &user, -60);
+    assert_eq!(
+        Err(ContractError::AllocationBelowSpent {
+            address: user.to_string(),
+            allocation: 40,
+            spent: 50
+        }),
+        res
+    );
+    let res = contract.adjust_allocations(&mut app, &owner, &user, -200);
+    assert_eq!(
+        Err(ContractError::NegativeAllocation {
+            address: user.to_string()
+        }),
+        res
+    );
+    contract
+        .adjust_allocations(&mut app, &owner, &user, 100)
+        .unwrap();
+    contract
+        .adjust_allocations(&mut app, &owner, &user3, 50)
+        .unwrap();
+
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: user.to_string(),
+            allocation: 200,
+            spent: 50,
+            received_amount: 50,
+            price: None,
+            bought: 50,
+        },
+        res
+    );
+
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(res.sale_data.total_allocated, 250);
+    assert_eq!(res.unallocated, 750);
+}
+
+#[test]
+pub fn transfer_allocation() {
+    let user = Addr::unchecked("user");
+    let friend = Addr::unchecked("friend");
+    let stranger = Addr::unchecked("stranger");
+    let otc = Addr::unchecked("otc");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &friend, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(2),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users, one of them at a discount
+    let users = vec![
+        UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: otc.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: Some(PriceOverride {
+                price_pay_amount: 1,
+                price_receive_amount: 2,
+            }),
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+    app.update_block(|block| block.time = Timestamp::from_seconds(5));
+    contract.buy(&mut app, &user, coins(40, "uusd")).unwrap();
+
+    // disabled until the owner turns them on
+    let res = contract.transfer_allocation(&mut app, &user, &friend, 10);
+    assert_eq!(Err(ContractError::TransfersDisabled {}), res);
+    contract
+        .update_transfer_config(
+            &mut app,
+            &owner,
+            TransferConfig {
+                enabled: true,
+                whitelisted_only: false,
+            },
+        )
+        .unwrap();
+
+    // discounted allocation can't move in either direction
+    let res = contract.transfer_allocation(&mut app, &user, &otc, 10);
+    assert_eq!(
+        Err(ContractError::PriceOverrideTransfer {
+            address: otc.to_string()
+        }),
+        res
+    );
+    let res = contract.transfer_allocation(&mut app, &otc, &friend, 10);
+    assert_eq!(
+        Err(ContractError::PriceOverrideTransfer {
+            address: otc.to_string()
+        }),
+        res
+    );
+
+    // only unspent allocation
+    let res = contract.transfer_allocation(&mut app, &user, &friend, 70);
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 70,
+            max: 60
+        }),
+        res
+    );
+    let res = contract.transfer_allocation(&mut app, &user, &user, 10);
+    assert_eq!(Err(ContractError::InvalidTransfer {}), res);
+    let res = contract.transfer_allocation(&mut app, &stranger, &friend, 10);
+    assert_eq!(Err(ContractError::NotParticipating {}), res);
+
+    let res = app
+        .execute_contract(
+            user.clone(),
+            contract.addr(),
+            &ExecuteMsg::TransferAllocation {
+                to: friend.to_string(),
+                amount: 50,
+            },
+            &[],
+        )
+        .unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|ev| ev.ty == "wasm-transfer_allocation")
+        .unwrap();
+    assert!(event
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "to" && attr.value == friend.as_str()));
+    assert!(event
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "amount" && attr.value == "50"));
+
+    contract.buy(&mut app, &friend, coins(50, "uusd")).unwrap();
+    let res = contract.buy(&mut app, &user, coins(20, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 20,
+            max: 10
+        }),
+        res
+    );
+
+    // restricted to the whitelist, then turned off
+    contract
+        .update_transfer_config(
+            &mut app,
+            &owner,
+            TransferConfig {
+                enabled: true,
+                whitelisted_only: true,
+            },
+        )
+        .unwrap();
+    let res = contract.transfer_allocation(&mut app, &user, &stranger, 5);
+    assert_eq!(
+        Err(ContractError::RecipientNotWhitelisted {
+            address: stranger.to_string()
+        }),
+        res
+    );
+    contract
+        .transfer_allocation(&mut app, &user, &friend, 5)
+        .unwrap();
+
+    contract
+        .update_transfer_config(
+            &mut app,
+            &owner,
+            TransferConfig {
+                enabled: false,
+                whitelisted_only: true,
+            },
+        )
+        .unwrap();
+    let res = contract.transfer_allocation(&mut app, &user, &friend, 5);
+    assert_eq!(Err(ContractError::TransfersDisabled {}), res);
+
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: friend.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.allocation, 55);
+    assert_eq!(res.spent, 50);
+}
+
+#[test]
+pub fn buy_with_allocation_nft() {
+    let alice = Addr::unchecked("alice");
+    let bob = Addr::unchecked("bob");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &alice, coins(1000, "uusd"))
+            .unwrap();
+        router
+            .bank
+            .init_balance(storage, &bob, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+    let nft = AllocationNft::instantiate(&mut app, &owner).unwrap();
+    nft.mint(&mut app, &owner, "1", &alice);
+    nft.mint(&mut app, &owner, "2", &alice);
+
+    let res = contract.buy_with_nft(&mut app, &alice, "1", coins(10, "uusd"));
+    assert_eq!(Err(ContractError::NoAllocationNft {}), res);
+
+    let res = contract.update_allocation_nft(&mut app, &alice, Some(&nft.addr()));
+    assert_eq!(Err(ContractError::Unauthorized {}), res);
+    contract
+        .update_allocation_nft(&mut app, &owner, Some(&nft.addr()))
+        .unwrap();
+    contract
+        .set_nft_allocations(
+            &mut app,
+            &owner,
+            vec![NftAllocationReq {
+                token_id: "1".to_string(),
+                allocation: 100,
+            }],
+        )
+        .unwrap();
+
+    // only registered tokens, and only by their holder
+    let res = contract.buy_with_nft(&mut app, &alice, "2", coins(10, "uusd"));
+    assert_eq!(
+        Err(ContractError::UnknownAllocationNft {
+            token_id: "2".to_string()
+        }),
+        res
+    );
+    let res = contract.buy_with_nft(&mut app, &bob, "1", coins(10, "uusd"));
+    assert_eq!(
+        Err(ContractError::NftNotOwned {
+            token_id: "1".to_string()
+        }),
+        res
+    );
+    contract
+        .buy_with_nft(&mut app, &alice, "1", coins(60, "uusd"))
+        .unwrap();
+
+    // the rest of the allocation moves with the token
+    nft.transfer(&mut app, &alice, &bob, "1");
+    let res = contract.buy_with_nft(&mut app, &alice, "1", coins(10, "uusd"));
+    assert_eq!(
+        Err(ContractError::NftNotOwned {
+            token_id: "1".to_string()
+        }),
+        res
+    );
+    let res = contract.buy_with_nft(&mut app, &bob, "1", coins(50, "uusd"));
+    assert_eq!(
+        Err(ContractError::UserAllocationExceeded {
+            wanted: 50,
+            max: 40
+        }),
+        res
+    );
+    contract
+        .buy_with_nft(&mut app, &bob, "1", coins(40, "uusd"))
+        .unwrap();
+
+    let res: NftAllocationRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetNftAllocation {
+                token_id: "1".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        NftAllocationRes {
+            token_id: "1".to_string(),
+            allocation: 100,
+            spent: 100,
+            received_amount: 100,
+        },
+        res
+    );
+
+    // bought tokens are claimed by the buyers
+    for (addr, owed) in [(&alice, 60), (&bob, 40)] {
+        let res: ClaimRes = contract
+            .query_value(
+                &app,
+                &QueryMsg::GetClaim {
+                    address: addr.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(res.owed, owed);
+    }
+
+    // and count as buyers
+    let res: Vec<UserInfoRes> = contract
+        .query_value(&app, &QueryMsg::GetTopBuyers { limit: None })
+        .unwrap();
+    assert_eq!(
+        res.iter()
+            .map(|user| (user.address.as_str(), user.bought))
+            .collect::<Vec<_>>(),
+        vec![("alice", 60), ("bob", 40)]
+    );
+
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(res.sale_data.total_allocated, 100);
+    assert_eq!(res.sale_data.total_spent, 100);
+}
+
+#[test]
+pub fn status_and_simulate_buy() {
+    let user = Addr::unchecked("user");
+    let stranger = Addr::unchecked("stranger");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(10)),
+        end: Expiration::AtTime(Timestamp::from_seconds(20)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 100,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let simulate = |app: &MoonApp, address: &Addr, amount: Coin| -> SimulateBuyRes {
+        contract
+            .query_value(
+                app,
+                &QueryMsg::SimulateBuy {
+                    address: address.to_string(),
+                    amount,
+                },
+            )
+            .unwrap()
+    };
+
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(
+        StatusRes {
+            phase: SalePhase::Upcoming,
+            paused: false,
+            remaining: Some(Duration::Time(5)),
+            percent_sold: Decimal::zero(),
+            remaining_supply: 1000,
+            sold_out: false,
+        },
+        res
+    );
+    assert_eq!(
+        simulate(&app, &user, coin(50, "uusd")).error,
+        Some(ContractError::SaleNotActive {}.to_string())
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(12));
+    assert_eq!(
+        simulate(&app, &user, coin(50, "uusd")),
+        SimulateBuyRes {
+            tokens: Some(100),
+            error: None,
+        }
+    );
+    assert_eq!(
+        simulate(&app, &user, coin(150, "uusd")).error,
+        Some(
+            ContractError::UserAllocationExceeded {
+                wanted: 150,
+                max: 100
+            }
+            .to_string()
+        )
+    );
+    assert_eq!(
+        simulate(&app, &user, coin(50, "uatom")).error,
+        Some(
+            ContractError::UnsupportedDenom {
+                denom: "uatom".to_string()
+            }
+            .to_string()
+        )
+    );
+    assert!(simulate(&app, &stranger, coin(50, "uusd"))
+        .error
+        .unwrap()
+        .contains("not found"));
+
+    contract.buy(&mut app, &user, coins(50, "uusd")).unwrap();
+
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(
+        StatusRes {
+            phase: SalePhase::Active,
+            paused: false,
+            remaining: Some(Duration::Time(8)),
+            percent_sold: Decimal::percent(500),
+            remaining_supply: 950,
+            sold_out: false,
+        },
+        res
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(20));
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(res.phase, SalePhase::Ended);
+    assert_eq!(res.remaining, None);
+}
+
+#[test]
+pub fn buyer_stats_and_top_buyers() {
+    let users = ["user1", "user2", "user3", "user4"].map(Addr::unchecked);
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for user in &users {
+            router
+                .bank
+                .init_balance(storage, user, coins(1000, "uusd"))
+                .unwrap();
+        }
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // set users, user4 with spent carried over from elsewhere
+    let user_list = users
+        .iter()
+        .map(|user| UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: if *user == users[3] { 500 } else { 0 },
+            price: None,
+        })
+        .collect();
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+
+    let res: BuyerStatsRes = contract
+        .query_value(&app, &QueryMsg::GetBuyerStats {})
+        .unwrap();
+    assert_eq!(
+        BuyerStatsRes {
+            participants: 0,
+            buys: 0,
+            average_contribution: 0,
+            median_contribution: 0,
+        },
+        res
+    );
+
+    // user4 never buys here
+    contract
+        .buy(&mut app, &users[0], coins(100, "uusd"))
+        .unwrap();
+    contract
+        .buy(&mut app, &users[1], coins(300, "uusd"))
+        .unwrap();
+    contract
+        .buy(&mut app, &users[2], coins(200, "uusd"))
+        .unwrap();
+    contract
+        .buy(&mut app, &users[0], coins(50, "uusd"))
+        .unwrap();
+
+    let res: BuyerStatsRes = contract
+        .query_value(&app, &QueryMsg::GetBuyerStats {})
+        .unwrap();
+    assert_eq!(
+        BuyerStatsRes {
+            participants: 3,
+            buys: 4,
+            average_contribution: 216,
+            median_contribution: 200,
+        },
+        res
+    );
+
+    let res: Vec<UserInfoRes> = contract
+        .query_value(&app, &QueryMsg::GetTopBuyers { limit: None })
+        .unwrap();
+    assert_eq!(
+        res.iter()
+            .map(|user| (user.address.as_str(), user.bought))
+            .collect::<Vec<_>>(),
+        vec![("user2", 300), ("user3", 200), ("user1", 150)]
+    );
+
+    let res: Vec<UserInfoRes> = contract
+        .query_value(&app, &QueryMsg::GetTopBuyers { limit: Some(1) })
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].address, "user2");
+
+    // an even number of buyers takes the middle two
+    contract
+        .buy(&mut app, &users[3], coins(100, "uusd"))
+        .unwrap();
+    let res: BuyerStatsRes = contract
+        .query_value(&app, &QueryMsg::GetBuyerStats {})
+        .unwrap();
+    assert_eq!(
+        BuyerStatsRes {
+            participants: 4,
+            buys: 5,
+            average_contribution: 187,
+            median_contribution: 175,
+        },
+        res
+    );
+}
+
+#[test]
+pub fn cancel_sale_and_refund() {
+    let users = ["user1", "user2", "user3"].map(Addr::unchecked);
+    let owner = Addr::unchecked("owner");
+    let launchpad = Addr::unchecked("launchpad");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for user in &users {
+            router
+                .bank
+                .init_balance(storage, user, coins(1000, "uusd"))
+                .unwrap();
+        }
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+    contract
+        .update_fee_recipients(
+            &mut app,
+            &owner,
+            vec![FeeRecipientReq {
+                address: launchpad.to_string(),
+                label: "launchpad".to_string(),
+                bps: 1000,
+            }],
+        )
+        .unwrap();
+
+    // set users
+    let user_list = users
+        .iter()
+        .map(|user| UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        })
+        .collect();
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+
+    // user3 never buys
+    contract
+        .buy(&mut app, &users[0], coins(100, "uusd"))
+        .unwrap();
+    contract
+        .buy(&mut app, &users[1], coins(300, "uusd"))
+        .unwrap();
+    contract
+        .buy(&mut app, &users[0], coins(50, "uusd"))
+        .unwrap();
+
+    // fees and the raise stay in the contract while the sale can be cancelled
+    assert_eq!(
+        contract.settle(&mut app, &users[2]).unwrap_err(),
+        ContractError::NotFinalized {}
+    );
+    assert_eq!(
+        contract
+            .withdraw(&mut app, &owner, coins(1, "uusd"))
+            .unwrap_err(),
+        ContractError::NotFinalized {}
+    );
+
+    // only the owner cancels, and refunds wait for it
+    assert_eq!(
+        contract
+            .cancel_sale(&mut app, &users[0], "rug")
+            .unwrap_err(),
+        ContractError::Unauthorized {}
+    );
+    assert_eq!(
+        contract.refund(&mut app, &users[0]).unwrap_err(),
+        ContractError::NotCancelled {}
+    );
+
+    contract.cancel_sale(&mut app, &owner, "rug").unwrap();
+    let cancelled = ContractError::SaleCancelled {
+        reason: "rug".to_string(),
+    };
+    assert_eq!(
+        contract.cancel_sale(&mut app, &owner, "again").unwrap_err(),
+        cancelled
+    );
+
+    // no more buys, fee payouts or raised funds for the owner
+    assert_eq!(
+        contract
+            .buy(&mut app, &users[2], coins(100, "uusd"))
+            .unwrap_err(),
+        cancelled
+    );
+    assert_eq!(contract.settle(&mut app, &owner).unwrap_err(), cancelled);
+    assert_eq!(
+        contract
+            .withdraw(&mut app, &owner, coins(1, "uusd"))
+            .unwrap_err(),
+        ContractError::WithdrawExceedsAvailable {
+            denom: "uusd".to_string(),
+            available: 0,
+        }
+    );
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    assert_eq!(contract.finalize(&mut app, &owner).unwrap_err(), cancelled);
+
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(res.phase, SalePhase::Cancelled);
+    let res: Vec<Coin> = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetRefund {
+                address: users[0].to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res, coins(150, "uusd"));
+
+    // fees included, buyers get back everything they paid
+    contract.refund(&mut app, &users[0]).unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[0], "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        1000
+    );
+    assert_eq!(
+        contract.refund(&mut app, &users[0]).unwrap_err(),
+        ContractError::NothingToRefund {}
+    );
+    assert_eq!(
+        contract.refund(&mut app, &users[2]).unwrap_err(),
+        ContractError::NothingToRefund {}
+    );
+
+    let res: RefundStatusRes = contract
+        .query_value(&app, &QueryMsg::GetRefundStatus {})
+        .unwrap();
+    assert_eq!(
+        RefundStatusRes {
+            cancellation: Some(Cancellation {
+                reason: "rug".to_string(),
+                cancelled_at: Timestamp::from_seconds(5),
+                refunded_users: 1,
+            }),
+            pending_users: 1,
+            refunded: coins(150, "uusd"),
+            pending: coins(300, "uusd"),
+        },
+        res
+    );
+
+    // the owner pushes the rest
+    assert_eq!(
+        contract
+            .push_refunds(&mut app, &users[1], None)
+            .unwrap_err(),
+        ContractError::Unauthorized {}
+    );
+    contract.push_refunds(&mut app, &owner, None).unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[1], "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        1000
+    );
+
+    let res: RefundStatusRes = contract
+        .query_value(&app, &QueryMsg::GetRefundStatus {})
+        .unwrap();
+    assert_eq!(res.pending_users, 0);
+    assert_eq!(res.refunded, coins(450, "uusd"));
+    assert_eq!(res.pending, vec![]);
+    assert_eq!(res.cancellation.unwrap().refunded_users, 2);
+
+    // anything sent on top of the refunds is the owner's again
+    MoonSaleContract::mint_coins(&mut app, &contract.addr(), coins(20, "uusd"));
+    contract
+        .withdraw(&mut app, &owner, coins(20, "uusd"))
+        .unwrap();
+}
+
+#[test]
+pub fn claim_insurance() {
+    let users = ["user1", "user2", "user3"].map(Addr::unchecked);
+    let owner = Addr::unchecked("owner");
+    let launchpad = Addr::unchecked("launchpad");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 6,
+        sale_decimals: 6,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for user in &users {
+            router
+                .bank
+                .init_balance(storage, user, coins(1000, "uusd"))
+                .unwrap();
+        }
+        // sale token above the sale price
+        InjectiveModule::set_pyth_price(
+            storage,
+            "moon_usd",
+            Decimal256::percent(60),
+            Decimal256::percent(60),
+            5,
+        );
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+    MoonSaleContract::mint_coins(&mut app, &contract.addr(), coins(600, "moon"));
+    contract
+        .update_fee_recipients(
+            &mut app,
+            &owner,
+            vec![FeeRecipientReq {
+                address: launchpad.to_string(),
+                label: "launchpad".to_string(),
+                bps: 1000,
+            }],
+        )
+        .unwrap();
+
+    // set users
+    let user_list = users
+        .iter()
+        .map(|user| UserInfoReq {
+            address: user.to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        })
+        .collect();
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+
+    let mut insurance = InsuranceConfig {
+        window: 100,
+        unconditional_window: 10,
+        unconditional_share: Decimal::percent(150),
+        oracle: Some(OracleConfig {
+            price_id: "moon_usd".to_string(),
+            max_staleness: 1000,
+            max_deviation_bps: 500,
+        }),
+        below_price_share: Decimal::one(),
+    };
+    assert_eq!(
+        contract
+            .update_insurance_config(&mut app, &owner, Some(insurance.clone()))
+            .unwrap_err(),
+        ContractError::InvalidInsuranceConfig {}
+    );
+    insurance.unconditional_share = Decimal::percent(50);
+    contract
+        .update_insurance_config(&mut app, &owner, Some(insurance.clone()))
+        .unwrap();
+
+    for user in &users {
+        contract.buy(&mut app, user, coins(100, "uusd")).unwrap();
+    }
+
+    // nothing happens before finalize
+    assert_eq!(
+        contract.claim_insurance(&mut app, &users[0]).unwrap_err(),
+        ContractError::NotFinalized {}
+    );
+    assert_eq!(
+        contract
+            .withdraw(&mut app, &owner, coins(1, "uusd"))
+            .unwrap_err(),
+        ContractError::NotFinalized {}
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    contract.finalize(&mut app, &owner).unwrap();
+    assert_eq!(
+        contract
+            .update_insurance_config(&mut app, &owner, None)
+            .unwrap_err(),
+        ContractError::AlreadyFinalized {}
+    );
+
+    // unconditional share early on, of what is left after fees
+    app.update_block(|block| block.time = Timestamp::from_seconds(15));
+    contract.claim_insurance(&mut app, &users[0]).unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[0], "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        945
+    );
+    let res: ClaimRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetClaim {
+                address: users[0].to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.owed, 0);
+    assert_eq!(
+        contract.claim_insurance(&mut app, &users[0]).unwrap_err(),
+        ContractError::NothingToReturn {}
+    );
+
+    // claimed tokens can't be given back
+    contract.claim(&mut app, &users[1]).unwrap();
+    assert_eq!(
+        contract.claim_insurance(&mut app, &users[1]).unwrap_err(),
+        ContractError::NothingToReturn {}
+    );
+    assert_eq!(
+        contract.settle(&mut app, &owner).unwrap_err(),
+        ContractError::InsuranceWindowOpen {}
+    );
+
+    // nothing once unconditional cover is over, unless the price drops below the sale price
+    app.update_block(|block| block.time = Timestamp::from_seconds(30));
+    assert_eq!(
+        contract.claim_insurance(&mut app, &users[2]).unwrap_err(),
+        ContractError::NothingToRefund {}
+    );
+    app.init_modules(|_, _, storage| {
+        InjectiveModule::set_pyth_price(
+            storage,
+            "moon_usd",
+            Decimal256::percent(40),
+            Decimal256::percent(40),
+            30,
+        )
+    });
+    contract.claim_insurance(&mut app, &users[2]).unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[2], "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        990
+    );
+
+    let res: InsuranceRes = contract
+        .query_value(&app, &QueryMsg::GetInsurance {})
+        .unwrap();
+    assert_eq!(
+        InsuranceRes {
+            config: Some(insurance),
+            open: true,
+            closes_at: Some(Timestamp::from_seconds(110)),
+            tokens_returned: 400,
+        },
+        res
+    );
+
+    // user2's cover and all fees stay reserved until the window closes
+    let res: Vec<RaisedRes> = contract.query_value(&app, &QueryMsg::GetRaised {}).unwrap();
+    assert_eq!(
+        res,
+        vec![RaisedRes {
+            denom: "uusd".to_string(),
+            total: 300,
+            withdrawn: 0,
+            refunded: 135,
+            withdrawable: 45,
+        }]
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(110));
+    assert_eq!(
+        contract.claim_insurance(&mut app, &users[2]).unwrap_err(),
+        ContractError::InsuranceClosed {}
+    );
+    contract.settle(&mut app, &owner).unwrap();
+    assert_eq!(
+        app.wrap().query_balance(&launchpad, "uusd").unwrap(),
+        coin(30, "uusd")
+    );
+    contract
+        .withdraw(&mut app, &owner, coins(135, "uusd"))
+        .unwrap();
+
+    // returned tokens are the owner's again
+    contract
+        .withdraw(&mut app, &owner, coins(400, "moon"))
+        .unwrap();
+}
+
+#[test]
+pub fn claim_insurance_with_decimals() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    // 0.5 USD per whole token, with 6 decimal USD and 8 decimal tokens
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10_000_000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1_000_000, "uusd"))
+            .unwrap();
+        InjectiveModule::set_pyth_price(
+            storage,
+            "moon_usd",
+            Decimal256::percent(60),
+            Decimal256::percent(60),
+            2,
+        );
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(2),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // the oracle price can't be compared to a sale price without decimals
+    let insurance = InsuranceConfig {
+        window: 100,
+        unconditional_window: 0,
+        unconditional_share: Decimal::zero(),
+        oracle: Some(OracleConfig {
+            price_id: "moon_usd".to_string(),
+            max_staleness: 1000,
+            max_deviation_bps: 500,
+        }),
+        below_price_share: Decimal::one(),
+    };
+    assert_eq!(
+        contract
+            .update_insurance_config(&mut app, &owner, Some(insurance.clone()))
+            .unwrap_err(),
+        ContractError::InvalidInsuranceConfig {}
+    );
+    let with_decimals = SaleConfig {
+        pay_decimals: 6,
+        sale_decimals: 8,
+        ..sale.clone()
+    };
+    contract
+        .update_sale(&mut app, &owner, with_decimals)
+        .unwrap();
+    contract
+        .update_insurance_config(&mut app, &owner, Some(insurance))
+        .unwrap();
+    assert_eq!(
+        contract.update_sale(&mut app, &owner, sale).unwrap_err(),
+        ContractError::InvalidInsuranceConfig {}
+    );
+
+    let user_list = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1_000_000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+
+    // 1 USD buys 2 whole tokens
+    app.update_block(|block| block.time = Timestamp::from_seconds(5));
+    contract
+        .buy(&mut app, &user, coins(1_000_000, "uusd"))
+        .unwrap();
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.received_amount, 200_000_000);
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(10));
+    contract.finalize(&mut app, &owner).unwrap();
+
+    // 0.6 USD is above the sale price, 0.4 USD below it
+    assert_eq!(
+        contract.claim_insurance(&mut app, &user).unwrap_err(),
+        ContractError::NothingToRefund {}
+    );
+    app.init_modules(|_, _, storage| {
+        InjectiveModule::set_pyth_price(
+            storage,
+            "moon_usd",
+            Decimal256::percent(40),
+            Decimal256::percent(40),
+            10,
+        )
+    });
+    contract.claim_insurance(&mut app, &user).unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&user, "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        1_000_000
+    );
+}
+
+#[test]
+pub fn buy_with_token_caps() {
+    let users = ["user1", "user2"].map(Addr::unchecked);
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 500,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Tokens,
+        anti_sniping: None,
+        allow_oversubscription: true,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for user in &users {
+            router
+                .bank
+                .init_balance(storage, user, coins(1000, "uusd"))
+                .unwrap();
+        }
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // allocations in sale tokens
+    let user_list = vec![
+        UserInfoReq {
+            address: users[0].to_string(),
+            allocation: 300,
+            spent: 0,
+            price: None,
+        },
+        UserInfoReq {
+            address: users[1].to_string(),
+            allocation: 1000,
+            spent: 0,
+            price: None,
+        },
+    ];
+    contract.set_user_list(&mut app, &owner, user_list).unwrap();
+
+    contract
+        .buy(&mut app, &users[0], coins(100, "uusd"))
+        .unwrap();
+    assert_eq!(
+        contract
+            .buy(&mut app, &users[0], coins(100, "uusd"))
+            .unwrap_err(),
+        ContractError::UserAllocationExceeded {
+            wanted: 200,
+            max: 100
+        }
+    );
+
+    // partial fills stop at the token allocation
+    contract
+        .buy_partial(&mut app, &users[0], coins(100, "uusd"))
+        .unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[0], "uusd")
+            .unwrap()
+            .amount
+            .u128(),
+        850
+    );
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: users[0].to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!((res.spent, res.received_amount), (300, 300));
+
+    // the sale cap is in tokens too
+    assert_eq!(
+        contract
+            .buy(&mut app, &users[1], coins(200, "uusd"))
+            .unwrap_err(),
+        ContractError::SaleAllocationExceeded {
+            wanted: 400,
+            max: 200
+        }
+    );
+    contract
+        .buy(&mut app, &users[1], coins(100, "uusd"))
+        .unwrap();
+
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(res.sale_data.total_spent, 500);
+    assert_eq!(res.sale_data.total_tokens_sold, 500);
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(res.percent_sold, Decimal::percent(10000));
+    assert_eq!(res.remaining_supply, 0);
+
+    assert_eq!(
+        contract
+            .update_sale(
+                &mut app,
+                &owner,
+                SaleConfig {
+                    cap_unit: CapUnit::Pay,
+                    ..sale
+                }
+            )
+            .unwrap_err(),
+        ContractError::CapUnitLocked {}
+    );
+}
+
+#[test]
+pub fn buy_partial_with_coarse_denom() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    // 3 ucoarse are worth 1 uusd, which buys 10 tokens
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 10,
+        extra_pay_denoms: vec![PayDenom {
+            denom: "ucoarse".to_string(),
+            price_pay_amount: 3,
+            price_receive_amount: 10,
+            decimals: 0,
+            oracle: None,
+        }],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Tokens,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(100, "ucoarse"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 9,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    // 4 ucoarse still round to 10 tokens, the buyer gets the 9 that fit
+    contract
+        .buy_partial(&mut app, &user, coins(5, "ucoarse"))
+        .unwrap();
+    assert_eq!(
+        app.wrap()
+            .query_balance(&user, "ucoarse")
+            .unwrap()
+            .amount
+            .u128(),
+        96
+    );
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: user.to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!((res.spent, res.received_amount), (9, 9));
+}
+
+#[test]
+pub fn end_on_sell_out_and_anti_sniping() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(100)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: Some(AntiSnipingConfig {
+            window: 10,
+            extension: 5,
+            max_end: Timestamp::from_seconds(108),
+        }),
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        router
+            .bank
+            .init_balance(storage, &user, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(5),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale.clone()).unwrap();
+
+    // only a timed end, and never past the max end
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            end: Expiration::AtHeight(100),
+            ..sale.clone()
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidAntiSniping {}), res);
+    let res = contract.update_sale(
+        &mut app,
+        &owner,
+        SaleConfig {
+            end: Expiration::AtTime(Timestamp::from_seconds(110)),
+            ..sale
+        },
+    );
+    assert_eq!(Err(ContractError::InvalidAntiSniping {}), res);
+
+    // set users
+    let users = vec![UserInfoReq {
+        address: user.to_string(),
+        allocation: 1000,
+        spent: 0,
+        price: None,
+    }];
+    contract.set_user_list(&mut app, &owner, users).unwrap();
+
+    let buy_at = |app: &mut MoonApp, time: u64, amount: u128| -> Expiration {
+        app.update_block(|block| block.time = Timestamp::from_seconds(time));
+        contract.buy(app, &user, coins(amount, "uusd")).unwrap();
+        let res: GetSaleResponse = contract.query_value(app, &QueryMsg::GetSale {}).unwrap();
+        res.sale_config.end
+    };
+
+    // late buys push the end back, up to the max end
+    assert_eq!(
+        buy_at(&mut app, 50, 100),
+        Expiration::AtTime(Timestamp::from_seconds(100))
+    );
+    assert_eq!(
+        buy_at(&mut app, 92, 100),
+        Expiration::AtTime(Timestamp::from_seconds(105))
+    );
+    assert_eq!(
+        buy_at(&mut app, 101, 100),
+        Expiration::AtTime(Timestamp::from_seconds(108))
+    );
+    assert_eq!(
+        buy_at(&mut app, 104, 100),
+        Expiration::AtTime(Timestamp::from_seconds(108))
+    );
+
+    // selling out ends the sale right away
+    assert_eq!(
+        buy_at(&mut app, 105, 600),
+        Expiration::AtTime(Timestamp::from_seconds(105))
+    );
+    let res: StatusRes = contract.query_value(&app, &QueryMsg::GetStatus {}).unwrap();
+    assert_eq!(res.phase, SalePhase::Ended);
+    assert!(res.sold_out);
+    let res: GetSaleResponse = contract.query_value(&app, &QueryMsg::GetSale {}).unwrap();
+    assert_eq!(
+        res.sale_data.sold_out_at,
+        Some(Timestamp::from_seconds(105))
+    );
+
+    contract.finalize(&mut app, &owner).unwrap();
+}
+
+#[test]
+pub fn register_and_allocate() {
+    let users = ["user1", "user2", "user3"].map(Addr::unchecked);
+    let poor = Addr::unchecked("poor");
+    let owner = Addr::unchecked("owner");
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(20)),
+        end: Expiration::AtTime(Timestamp::from_seconds(30)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    let mut app = mock_app(|router, _, storage| {
+        for (user, holding) in users.iter().zip([100, 200, 300]) {
+            router
+                .bank
+                .init_balance(
+                    storage,
+                    user,
+                    vec![coin(holding, "uholding"), coin(1000, "uusd")],
+                )
+                .unwrap();
+        }
+        router
+            .bank
+            .init_balance(storage, &poor, coins(1000, "uusd"))
+            .unwrap();
+    });
+    app.set_block(BlockInfo {
+        height: 1,
+        time: Timestamp::from_seconds(2),
+        chain_id: "random-test".to_string(),
+    });
+    let code_id = MoonSaleContract::store_code(&mut app);
+
+    let contract =
+        MoonSaleContract::instantiate(&mut app, code_id, &owner, "Contract", sale).unwrap();
+
+    // weights are backed by escrowed holdings, and registration ends before the sale starts
+    let mut config = RegistrationConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(5)),
+        end: Expiration::AtTime(Timestamp::from_seconds(15)),
+        deposit: Some(coin(10, "uusd")),
+        min_holding: None,
+        rule: AllocationRule::Weighted,
+        total_allocation: 900,
+    };
+    assert_eq!(
+        contract
+            .update_registration_config(&mut app, &owner, Some(config.clone()))
+            .unwrap_err(),
+        ContractError::InvalidRegistrationConfig {}
+    );
+    // the escrow can't share the deposit denom
+    config.min_holding = Some(coin(50, "uusd"));
+    assert_eq!(
+        contract
+            .update_registration_config(&mut app, &owner, Some(config.clone()))
+            .unwrap_err(),
+        ContractError::InvalidRegistrationConfig {}
+    );
+    config.min_holding = Some(coin(50, "uholding"));
+    config.end = Expiration::AtTime(Timestamp::from_seconds(25));
+    assert_eq!(
+        contract
+            .update_registration_config(&mut app, &owner, Some(config.clone()))
+            .unwrap_err(),
+        ContractError::InvalidRegistrationConfig {}
+    );
+    config.end = Expiration::AtTime(Timestamp::from_seconds(15));
+    contract
+        .update_registration_config(&mut app, &owner, Some(config.clone()))
+        .unwrap();
+
+    let deposit = |holding: u128| vec![coin(holding, "uholding"), coin(10, "uusd")];
+    assert_eq!(
+        contract
+            .register(&mut app, &users[0], Some(100), deposit(100))
+            .unwrap_err(),
+        ContractError::RegistrationNotOpen {}
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(5));
+    assert_eq!(
+        contract
+            .register(&mut app, &users[0], Some(100), coins(100, "uholding"))
+            .unwrap_err(),
+        ContractError::InvalidDeposit {
+            deposit: "10uusd".to_string()
+        }
+    );
+    assert_eq!(
+        contract
+            .register(&mut app, &poor, Some(100), coins(10, "uusd"))
+            .unwrap_err(),
+        ContractError::InsufficientHoldings {
+            required: "50uholding".to_string()
+        }
+    );
+
+    // the weight can't go past the escrow
+    for weight in [None, Some(0), Some(101)] {
+        assert_eq!(
+            contract
+                .register(&mut app, &users[0], weight, deposit(100))
+                .unwrap_err(),
+            ContractError::InvalidWeight {}
+        );
+    }
+    for (user, holding) in users.iter().zip([100, 200, 300]) {
+        contract
+            .register(&mut app, user, Some(holding), deposit(holding))
+            .unwrap();
+    }
+    // the escrowed tokens can't back another registration
+    assert_eq!(
+        app.wrap()
+            .query_balance(&users[0], "uholding")
+            .unwrap()
+            .amount
+            .u128(),
+        0
+    );
+    assert_eq!(
+        contract
+            .register(&mut app, &users[0], Some(100), coins(10, "uusd"))
+            .unwrap_err(),
+        ContractError::AlreadyRegistered {}
+    );
+    assert_eq!(
+        contract
+            .update_registration_config(&mut app, &owner, None)
+            .unwrap_err(),
+        ContractError::RegistrationLocked {}
+    );
+
+    // unregistering returns the deposit and the escrow
+    contract.unregister(&mut app, &users[2]).unwrap();
+    assert_eq!(
+        app.wrap().query_all_balances(&users[2]).unwrap(),
+        vec![coin(300, "uholding"), coin(1000, "uusd")]
+    );
+    MoonSaleContract::mint_coins(&mut app, &users[2], coins(300, "uholding"));
+    contract
+        .register(&mut app, &users[2], Some(600), deposit(600))
+        .unwrap();
+
+    assert_eq!(
+        contract
+            .allocate_registrations(&mut app, &poor, None)
+            .unwrap_err(),
+        ContractError::RegistrationNotClosed {}
+    );
+
+    // anyone applies the rule once closed, page by page
+    app.update_block(|block| block.time = Timestamp::from_seconds(15));
+    contract
+        .allocate_registrations(&mut app, &poor, Some(2))
+        .unwrap();
+    let res: RegistrationRes = contract
+        .query_value(&app, &QueryMsg::GetRegistration {})
+        .unwrap();
+    assert_eq!(
+        RegistrationRes {
+            config: Some(config),
+            open: false,
+            closed: true,
+            registrants: 3,
+            total_weight: 900,
+            allocated: 2,
+        },
+        res
+    );
+    contract
+        .allocate_registrations(&mut app, &poor, Some(2))
+        .unwrap();
+    assert_eq!(
+        contract
+            .allocate_registrations(&mut app, &poor, None)
+            .unwrap_err(),
+        ContractError::NothingToAllocate {}
+    );
+
+    let res: Vec<RegistrantRes> = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetRegistrants {
+                start_after: Some(users[0].to_string()),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        res,
+        vec![
+            RegistrantRes {
+                address: users[1].to_string(),
+                weight: 200,
+                deposit: Some(coin(10, "uusd")),
+                allocation: Some(200),
+                holding: Some(coin(200, "uholding")),
+            },
+            RegistrantRes {
+                address: users[2].to_string(),
+                weight: 600,
+                deposit: Some(coin(10, "uusd")),
+                allocation: Some(600),
+                holding: Some(coin(600, "uholding")),
+            },
+        ]
+    );
+    let res: UserInfoRes = contract
+        .query_value(
+            &app,
+            &QueryMsg::GetUserInfo {
+                address: users[0].to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(res.allocation, 100);
+
+    // deposits are held until the sale ends
+    app.update_block(|block| block.time = Timestamp::from_seconds(20));
+    contract
+        .buy(&mut app, &users[2], coins(600, "uusd"))
+        .unwrap();
+    assert_eq!(
+        contract.return_deposit(&mut app, &users[0]).unwrap_err(),
+        ContractError::DepositLocked {}
+    );
+    let res: Vec<RaisedRes> = contract.query_value(&app, &QueryMsg::GetRaised {}).unwrap();
+    assert_eq!(res[0].withdrawable, 600);
+    assert_eq!(
+        contract
+            .withdraw(&mut app, &owner, coins(1, "uholding"))
+            .unwrap_err(),
+        ContractError::WithdrawExceedsAvailable {
+            denom: "uholding".to_string(),
+            available: 0
+        }
+    );
+
+    app.update_block(|block| block.time = Timestamp::from_seconds(30));
+    contract.return_deposit(&mut app, &users[0]).unwrap();
+    assert_eq!(
+        app.wrap().query_all_balances(&users[0]).unwrap(),
+        vec![coin(100, "uholding"), coin(1000, "uusd")]
+    );
+    assert_eq!(
+        contract.return_deposit(&mut app, &users[0]).unwrap_err(),
+        ContractError::NothingToClaim {}
+    );
+
+    // the other deposits stay reserved
+    let res: Vec<RaisedRes> = contract.query_value(&app, &QueryMsg::GetRaised {}).unwrap();
+    assert_eq!(res[0].withdrawable, 600);
+}
@@ -5,8 +5,10 @@ pub mod helpers;
 #[cfg(test)]
 pub mod integration_tests;
 pub mod msg;
+pub mod oracle;
 pub mod state;
 #[cfg(test)]
 pub mod tests;
+pub mod token_factory;
 
 pub use crate::error::ContractError;
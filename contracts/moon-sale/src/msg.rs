@@ -1,10 +1,25 @@
-use crate::state::{SaleConfig, SaleData, UserInfo};
+use crate::state::{
+    AntiBotConfig, BeneficiaryConfig, Cancellation, FcfsConfig, InsuranceConfig, PendingOwner,
+    PriceOverride, ReferralConfig, RegistrationConfig, SaleConfig, SaleData, State, TransferConfig,
+    UserInfo,
+};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Coin;
+use cosmwasm_std::{Addr, Coin, Decimal, Timestamp};
+use cw_utils::{Duration, Expiration};
 
 #[cw_serde]
 pub struct InstantiateMsg {
     pub sale: SaleConfig,
+    pub token_factory: Option<TokenFactoryReq>,
+}
+
+// creates `factory/{contract}/{subdenom}` as the sale token
+#[cw_serde]
+pub struct TokenFactoryReq {
+    pub subdenom: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
 }
 
 #[cw_serde]
@@ -12,6 +27,21 @@ pub struct UserInfoReq {
     pub address: String,
     pub allocation: u128,
     pub spent: u128,
+    pub price: Option<PriceOverride>,
+}
+
+#[cw_serde]
+pub struct NftAllocationReq {
+    pub token_id: String,
+    pub allocation: u128,
+}
+
+#[cw_serde]
+pub struct NftAllocationRes {
+    pub token_id: String,
+    pub allocation: u128,
+    pub spent: u128,
+    pub received_amount: u128,
 }
 
 #[cw_serde]
@@ -20,23 +50,143 @@ pub struct UserInfoRes {
     pub allocation: u128,
     pub spent: u128,
     pub received_amount: u128,
+    pub price: Option<PriceOverride>,
+    pub bought: u128,
+}
+
+#[cw_serde]
+pub struct FeeRecipientReq {
+    pub address: String,
+    pub label: String,
+    pub bps: u64,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    UpdateSale { sale: SaleConfig },
-    SetUserList { users: Vec<UserInfoReq> },
-    Buy {},
-    Withdraw { to: String, funds: Vec<Coin> },
-    TransferOwnership { address: String },
+    UpdateSale {
+        sale: SaleConfig,
+    },
+    SetUserList {
+        users: Vec<UserInfoReq>,
+    },
+    RemoveUsers {
+        addresses: Vec<String>,
+    },
+    // owner only, before the sale starts and the user buys
+    SetPriceOverride {
+        address: String,
+        price: Option<PriceOverride>,
+    },
+    AdjustAllocations {
+        address: String,
+        delta: i128,
+    },
+    TransferAllocation {
+        to: String,
+        amount: u128,
+    },
+    UpdateTransferConfig {
+        config: TransferConfig,
+    },
+    UpdateAllocationNft {
+        contract: Option<String>, // disables NFT allocations when unset
+    },
+    SetNftAllocations {
+        allocations: Vec<NftAllocationReq>,
+    },
+    Buy {
+        referrer: Option<String>,
+        // accept what fits under the allocation and supply, refund the rest
+        partial_fill: Option<bool>,
+        // fails if fewer sale tokens would be bought
+        min_tokens_out: Option<u128>,
+        // credited instead of the sender, only for approved senders
+        beneficiary: Option<String>,
+        // spends the allocation of an allocation NFT held by the buyer
+        token_id: Option<String>,
+    },
+    Withdraw {
+        funds: Vec<Coin>,
+    },
+    TransferOwnership {
+        address: String,
+        expiry: Option<Expiration>,
+    },
+    AcceptOwnership {},
+    CancelOwnershipTransfer {},
+    UpdateRoles {
+        operator: Option<String>,
+        treasury: Option<String>,
+        pauser: Option<String>,
+    },
+    Pause {
+        reason: String,
+    },
+    Unpause {},
+    UpdateFeeRecipients {
+        recipients: Vec<FeeRecipientReq>,
+    },
+    Settle {},
+    UpdateReferralConfig {
+        config: ReferralConfig,
+    },
+    UpdateBeneficiaryConfig {
+        approved_only: bool,
+        approved_senders: Vec<String>,
+    },
+    UpdateAntiBotConfig {
+        config: AntiBotConfig,
+    },
+    UpdateFcfsConfig {
+        config: Option<FcfsConfig>, // removes the FCFS phase when unset
+    },
+    ClaimReferralRewards {},
+    Finalize {},
+    Claim {},
+    // stops the sale for good and lets buyers get back what they paid
+    CancelSale {
+        reason: String,
+    },
+    Refund {},
+    // refunds up to `limit` contributions on behalf of their buyers
+    PushRefunds {
+        limit: Option<u32>,
+    },
+    UpdateInsuranceConfig {
+        config: Option<InsuranceConfig>, // removes the insurance when unset
+    },
+    // gives back the sender's unclaimed tokens for a share of what they paid, net of fees
+    ClaimInsurance {},
+    UpdateRegistrationConfig {
+        config: Option<RegistrationConfig>, // removes the registration phase when unset
+    },
+    Register {
+        weight: Option<u128>, // required by the weighted rule, up to the escrowed holding
+    },
+    Unregister {},
+    // turns up to `limit` registrants into allocations once registration closed
+    AllocateRegistrations {
+        limit: Option<u32>,
+    },
+    ReturnDeposit {},
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
+    #[returns(State)]
+    GetState {},
+
     #[returns(GetSaleResponse)]
     GetSale {},
 
+    #[returns(StatusRes)]
+    GetStatus {},
+
+    // runs the checks of a plain buy without executing it
+    #[returns(SimulateBuyRes)]
+    SimulateBuy { address: String, amount: Coin },
+
     #[returns(UserInfoRes)]
     GetUserInfo { address: String },
 
@@ -44,16 +194,224 @@ pub enum QueryMsg {
     GetUsers {},
 
     #[returns(u128)]
-    GetReceivedAmount { pay_amount: u128 },
+    GetReceivedAmount {
+        pay_amount: u128,
+        denom: Option<String>,   // defaults to pay_denom
+        address: Option<String>, // quotes the user's price override if any
+    },
+
+    #[returns(GetRolesResponse)]
+    GetRoles {},
+
+    #[returns(Vec<FeeRecipientRes>)]
+    GetFees {},
+
+    #[returns(ReferralConfig)]
+    GetReferralConfig {},
+
+    #[returns(BeneficiaryConfig)]
+    GetBeneficiaryConfig {},
+
+    #[returns(AntiBotConfig)]
+    GetAntiBotConfig {},
+
+    #[returns(Option<Addr>)]
+    GetAllocationNft {},
+
+    #[returns(NftAllocationRes)]
+    GetNftAllocation { token_id: String },
+
+    #[returns(TransferConfig)]
+    GetTransferConfig {},
+
+    #[returns(FcfsRes)]
+    GetFcfs {},
+
+    #[returns(UserLimitsRes)]
+    GetUserLimits { address: String },
+
+    #[returns(ReferrerRes)]
+    GetReferrer { address: String },
+
+    #[returns(Vec<ReferrerRes>)]
+    GetTopReferrers { limit: Option<u32> },
+
+    #[returns(BuyerStatsRes)]
+    GetBuyerStats {},
+
+    #[returns(Vec<UserInfoRes>)]
+    GetTopBuyers { limit: Option<u32> },
+
+    #[returns(ClaimRes)]
+    GetClaim { address: String },
+
+    #[returns(Vec<RaisedRes>)]
+    GetRaised {},
+
+    #[returns(RefundStatusRes)]
+    GetRefundStatus {},
+
+    // what the address gets back from a refund
+    #[returns(Vec<Coin>)]
+    GetRefund { address: String },
+
+    #[returns(InsuranceRes)]
+    GetInsurance {},
+
+    #[returns(RegistrationRes)]
+    GetRegistration {},
+
+    #[returns(Option<RegistrantRes>)]
+    GetRegistrant { address: String },
+
+    #[returns(Vec<RegistrantRes>)]
+    GetRegistrants {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
 pub struct GetSaleResponse {
     pub sale_config: SaleConfig,
     pub sale_data: SaleData,
+    pub started: bool,
+    pub ended: bool,
+    pub unallocated: u128, // max_supply not handed out as allocations
+}
+
+#[cw_serde]
+pub enum SalePhase {
+    Upcoming,
+    Active,
+    Ended,
+    Finalized,
+    Cancelled,
+}
+
+#[cw_serde]
+pub struct StatusRes {
+    pub phase: SalePhase,
+    pub paused: bool,
+    pub remaining: Option<Duration>, // until the start while upcoming, until the end while active
+    pub percent_sold: Decimal,
+    pub remaining_supply: u128,
+    pub sold_out: bool,
+}
+
+#[cw_serde]
+pub struct SimulateBuyRes {
+    pub tokens: Option<u128>,
+    pub error: Option<String>, // why the buy would fail
 }
 
 #[cw_serde]
 pub struct GetUserInfoResponse {
     pub user: UserInfo,
 }
+
+#[cw_serde]
+pub struct GetRolesResponse {
+    pub owner: Addr,
+    pub pending_owner: Option<PendingOwner>,
+    pub operator: Addr,
+    pub treasury: Addr,
+    pub pauser: Addr,
+}
+
+#[cw_serde]
+pub struct FeeRecipientRes {
+    pub address: String,
+    pub label: String,
+    pub bps: u64,
+    pub pending: Vec<Coin>,
+    pub settled: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct ReferrerRes {
+    pub address: String,
+    pub volume: u128,
+    pub buys: u64,
+    pub token_reward: u128,
+    pub pending: Vec<Coin>,
+    pub claimed: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct ClaimRes {
+    pub address: String,
+    pub owed: u128,
+    pub claimed: u128,
+}
+
+#[cw_serde]
+pub struct RaisedRes {
+    pub denom: String,
+    pub total: u128,
+    pub withdrawn: u128,
+    pub refunded: u128,
+    pub withdrawable: u128,
+}
+
+#[cw_serde]
+pub struct RegistrationRes {
+    pub config: Option<RegistrationConfig>,
+    pub open: bool,
+    pub closed: bool,
+    pub registrants: u64,
+    pub total_weight: u128,
+    pub allocated: u64,
+}
+
+#[cw_serde]
+pub struct RegistrantRes {
+    pub address: String,
+    pub weight: u128,
+    pub deposit: Option<Coin>,
+    pub allocation: Option<u128>, // set once allocated
+    pub holding: Option<Coin>,
+}
+
+#[cw_serde]
+pub struct InsuranceRes {
+    pub config: Option<InsuranceConfig>,
+    pub open: bool,
+    pub closes_at: Option<Timestamp>, // set once finalized
+    pub tokens_returned: u128,
+}
+
+#[cw_serde]
+pub struct RefundStatusRes {
+    pub cancellation: Option<Cancellation>,
+    pub pending_users: u64, // buyers still owed a refund
+    pub refunded: Vec<Coin>,
+    pub pending: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct UserLimitsRes {
+    pub address: String,
+    pub available_allocation: u128,
+    pub max_per_tx: Option<u128>,
+    pub block_available: Option<u128>, // left in the current block
+    pub buys: u64,
+    pub max_buys: Option<u64>,
+    pub next_buy: Option<Expiration>, // set while cooling down
+}
+
+#[cw_serde]
+pub struct FcfsRes {
+    pub config: Option<FcfsConfig>,
+    pub active: bool,
+    pub pool: u128, // unspent allocations up for grabs
+}
+
+// contributions are what users bought themselves, with their allocation or an NFT's
+#[cw_serde]
+pub struct BuyerStatsRes {
+    pub participants: u64,
+    pub buys: u64,
+    pub average_contribution: u128,
+    pub median_contribution: u128,
+}
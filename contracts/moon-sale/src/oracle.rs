@@ -0,0 +1,60 @@
+use cosmwasm_std::{Decimal256, QuerierWrapper, StdResult, Timestamp, Uint128, Uint256};
+use injective_cosmwasm::{InjectiveQuerier, InjectiveQueryWrapper};
+
+use crate::{
+    state::{pow10, OracleConfig},
+    ContractError,
+};
+
+// USD price of one whole token, rejected when stale or too far from the ema price
+pub fn usd_price(
+    querier: &QuerierWrapper<InjectiveQueryWrapper>,
+    now: Timestamp,
+    oracle: &OracleConfig,
+) -> Result<Decimal256, ContractError> {
+    let state = InjectiveQuerier::new(querier)
+        .query_pyth_price(&oracle.price_id)?
+        .price_state
+        .ok_or_else(|| ContractError::OraclePriceUnavailable {
+            price_id: oracle.price_id.clone(),
+        })?;
+
+    let age = now
+        .seconds()
+        .saturating_sub(state.publish_time.max(0) as u64);
+    if age > oracle.max_staleness {
+        return Err(ContractError::StaleOraclePrice {
+            price_id: oracle.price_id.clone(),
+            age,
+        });
+    }
+
+    let price = Decimal256::try_from(state.price_state.price)?;
+    let ema_price = Decimal256::try_from(state.ema_price)?;
+    if price.is_zero() {
+        return Err(ContractError::OraclePriceUnavailable {
+            price_id: oracle.price_id.clone(),
+        });
+    }
+
+    let deviation = price.abs_diff(ema_price);
+    if deviation * Decimal256::from_ratio(10000u128, 1u128)
+        > ema_price * Decimal256::from_ratio(oracle.max_deviation_bps, 1u128)
+    {
+        return Err(ContractError::OraclePriceDeviation { price, ema_price });
+    }
+
+    Ok(price)
+}
+
+// Value of `amount` with `decimals` in pay denom units
+pub fn usd_value(
+    price: Decimal256,
+    amount: u128,
+    decimals: u32,
+    pay_decimals: u32,
+) -> StdResult<u128> {
+    let value = Uint256::from(amount).mul_floor(price) * pow10(pay_decimals) / pow10(decimals);
+
+    Ok(Uint128::try_from(value)?.u128())
+}
@@ -1,50 +1,702 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Timestamp};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, BlockInfo, Coin, Decimal, StdResult, Timestamp, Uint128, Uint256};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw_utils::{Duration, Expiration, Scheduled};
+
+use crate::ContractError;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct State {
     pub owner: Addr,
+    pub pause_reason: Option<String>, // set while paused
+}
+
+impl State {
+    pub fn check_paused(&self) -> Result<(), ContractError> {
+        if let Some(reason) = &self.pause_reason {
+            return Err(ContractError::Paused {
+                reason: reason.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn check_owner(&self, sender: &Addr) -> Result<(), ContractError> {
+        if *sender != self.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwner {
+    pub address: Addr,
+    pub expires: Expiration,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Roles {
+    // manages the whitelist
+    pub operator: Addr,
+    // receives withdrawn funds
+    pub treasury: Addr,
+    // can pause the sale
+    pub pauser: Addr,
+}
+
+impl Roles {
+    pub fn check_operator(&self, sender: &Addr) -> Result<(), ContractError> {
+        if *sender != self.operator {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        Ok(())
+    }
+
+    pub fn check_pauser(&self, sender: &Addr) -> Result<(), ContractError> {
+        if *sender != self.pauser {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct SaleConfig {
-    pub start_time: Timestamp,
-    pub end_time: Timestamp,
+    pub start: Scheduled,
+    pub end: Expiration,
     pub pay_denom: String,
     pub sale_denom: Option<String>,
     pub max_supply: u128,
     pub price_pay_amount: u128,
     pub price_receive_amount: u128,
+    // accepted alongside `pay_denom`, which stays the unit for pay amounts
+    #[serde(default)]
+    pub extra_pay_denoms: Vec<PayDenom>,
+    // prices are per whole token when set
+    #[serde(default)]
+    pub pay_decimals: u32,
+    #[serde(default)]
+    pub sale_decimals: u32,
+    // unit of `max_supply`, allocations and spent amounts
+    #[serde(default)]
+    pub cap_unit: CapUnit,
+    #[serde(default)]
+    pub anti_sniping: Option<AntiSnipingConfig>,
+    // lets allocations add up to more than `max_supply`, buys are first come first served
+    #[serde(default)]
+    pub allow_oversubscription: bool,
+}
+
+// buys in the last `window` seconds push a timed end back by `extension` seconds,
+// never past `max_end`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AntiSnipingConfig {
+    pub window: u64,
+    pub extension: u64,
+    pub max_end: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CapUnit {
+    #[default]
+    Pay, // pay_denom units
+    Tokens, // sale tokens bought
 }
 
 impl SaleConfig {
-    pub fn is_active(&self, time: Timestamp) -> bool {
-        self.start_time <= time && time < self.end_time
+    pub fn is_active(&self, block: &BlockInfo) -> bool {
+        self.is_started(block) && !self.is_ended(block)
+    }
+
+    pub fn is_started(&self, block: &BlockInfo) -> bool {
+        self.start.is_triggered(block)
+    }
+
+    pub fn is_ended(&self, block: &BlockInfo) -> bool {
+        self.end.is_expired(block)
+    }
+
+    // ends the sale in the current block
+    pub fn end_now(&mut self, block: &BlockInfo) {
+        self.end = match self.end {
+            Expiration::AtHeight(_) => Expiration::AtHeight(block.height),
+            _ => Expiration::AtTime(block.time),
+        };
+    }
+
+    // pushes the end back for a buy close to it, returns the new end
+    pub fn extend_end(&mut self, block: &BlockInfo) -> Option<Timestamp> {
+        let anti_sniping = self.anti_sniping.as_ref()?;
+        let Expiration::AtTime(end) = self.end else {
+            return None;
+        };
+        if block.time.plus_seconds(anti_sniping.window) < end {
+            return None;
+        }
+
+        let extended = end
+            .plus_seconds(anti_sniping.extension)
+            .min(anti_sniping.max_end);
+        if extended <= end {
+            return None;
+        }
+        self.end = Expiration::AtTime(extended);
+        Some(extended)
+    }
+
+    pub fn validate(&self) -> Result<(), ContractError> {
+        // start and end can only be compared when both are heights or both are times
+        let ordered = match (&self.start, &self.end) {
+            (_, Expiration::Never {}) => false,
+            (Scheduled::AtHeight(start), Expiration::AtHeight(end)) => start <= end,
+            (Scheduled::AtTime(start), Expiration::AtTime(end)) => start <= end,
+            _ => true,
+        };
+        if !ordered {
+            return Err(ContractError::InvalidSchedule {});
+        }
+
+        // the main denom always has a fixed price
+        if self.price_pay_amount == 0 || self.price_receive_amount == 0 {
+            return Err(ContractError::InvalidPrice {});
+        }
+
+        // prices scale by 10^decimals in 256 bits, which only has room for real token decimals
+        let decimals = [self.pay_decimals, self.sale_decimals].into_iter().chain(
+            self.extra_pay_denoms
+                .iter()
+                .map(|pay_denom| pay_denom.decimals),
+        );
+        if decimals.max().unwrap_or_default() > MAX_DECIMALS {
+            return Err(ContractError::InvalidDecimals { max: MAX_DECIMALS });
+        }
+
+        // only a timed end can be extended
+        if let Some(anti_sniping) = &self.anti_sniping {
+            match self.end {
+                Expiration::AtTime(end) if anti_sniping.max_end >= end => {}
+                _ => return Err(ContractError::InvalidAntiSniping {}),
+            }
+        }
+
+        let mut denoms = vec![&self.pay_denom];
+        for pay_denom in &self.extra_pay_denoms {
+            let fixed_price = pay_denom.price_pay_amount > 0 && pay_denom.price_receive_amount > 0;
+            // oracle prices are per whole token in USD, so both sides need their decimals
+            let scaled = self.pay_decimals > 0 && pay_denom.decimals > 0;
+            let priced = match pay_denom.oracle {
+                Some(_) => scaled,
+                None => fixed_price,
+            };
+            if denoms.contains(&&pay_denom.denom) || !priced {
+                return Err(ContractError::InvalidPayDenom {
+                    denom: pay_denom.denom.clone(),
+                });
+            }
+            denoms.push(&pay_denom.denom);
+        }
+
+        Ok(())
+    }
+
+    // price of an accepted denom
+    pub fn price(&self, denom: &str) -> Option<PayDenom> {
+        if denom == self.pay_denom {
+            return Some(PayDenom {
+                denom: self.pay_denom.clone(),
+                price_pay_amount: self.price_pay_amount,
+                price_receive_amount: self.price_receive_amount,
+                decimals: self.pay_decimals,
+                oracle: None,
+            });
+        }
+
+        self.extra_pay_denoms
+            .iter()
+            .find(|pay_denom| pay_denom.denom == denom)
+            .cloned()
+    }
+
+    // converts an amount paid at `price` into `pay_denom` units
+    pub fn normalize(&self, price: &PayDenom, amount: u128) -> StdResult<u128> {
+        let normalized = Uint256::from(amount)
+            * Uint256::from(price.price_receive_amount)
+            * Uint256::from(self.price_pay_amount)
+            * pow10(self.pay_decimals)
+            / (Uint256::from(price.price_pay_amount)
+                * Uint256::from(self.price_receive_amount)
+                * pow10(price.decimals));
+
+        Ok(Uint128::try_from(normalized)?.u128())
+    }
+
+    // tokens bought with `value` pay_denom units, and the value left over by rounding down
+    pub fn tokens_for(
+        &self,
+        value: u128,
+        user_price: Option<&PriceOverride>,
+    ) -> StdResult<(u128, u128)> {
+        let (price_pay_amount, price_receive_amount) = match user_price {
+            Some(price) => (price.price_pay_amount, price.price_receive_amount),
+            None => (self.price_pay_amount, self.price_receive_amount),
+        };
+        let tokens_per_unit = Uint256::from(price_receive_amount) * pow10(self.sale_decimals);
+        let units_per_token = Uint256::from(price_pay_amount) * pow10(self.pay_decimals);
+
+        let tokens = Uint256::from(value) * tokens_per_unit / units_per_token;
+        let cost = tokens.mul_ceil((units_per_token, tokens_per_unit));
+
+        Ok((
+            Uint128::try_from(tokens)?.u128(),
+            value - Uint128::try_from(cost)?.u128(),
+        ))
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub const MAX_DECIMALS: u32 = 18;
+
+pub fn pow10(decimals: u32) -> Uint256 {
+    Uint256::from(10u128).pow(decimals)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PayDenom {
+    pub denom: String,
+    pub price_pay_amount: u128,
+    pub price_receive_amount: u128,
+    #[serde(default)]
+    pub decimals: u32,
+    // priced through the oracle instead of the fixed ratio when set
+    #[serde(default)]
+    pub oracle: Option<OracleConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OracleConfig {
+    pub price_id: String, // pyth feed, USD per whole token, pay_denom is taken as USD
+    pub max_staleness: u64, // seconds
+    pub max_deviation_bps: u64, // from the ema price
+}
+
+// who may buy on behalf of someone else
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct BeneficiaryConfig {
+    pub approved_only: bool,
+    pub approved_senders: Vec<Addr>,
+}
+
+impl BeneficiaryConfig {
+    pub fn allows(&self, sender: &Addr) -> bool {
+        !self.approved_only || self.approved_senders.contains(sender)
+    }
+}
+
+// limits on buy frequency and size, amounts in pay denom units
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct AntiBotConfig {
+    pub max_per_tx: Option<u128>,
+    pub cooldown: Option<Duration>, // between buys of the same address
+    pub max_per_block: Option<u128>, // across all buyers
+    pub max_buys: Option<u64>,      // per address
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct BuyerActivity {
+    pub buys: u64,
+    pub next_buy: Option<Expiration>, // set while a cooldown applies
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct BlockVolume {
+    pub height: u64,
+    pub amount: u128,
+}
+
+impl BlockVolume {
+    // volume bought so far in the current block
+    pub fn at(&self, block: &BlockInfo) -> u128 {
+        if self.height == block.height {
+            self.amount
+        } else {
+            0
+        }
+    }
+}
+
+// negotiated pay_denom price for a single buyer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PriceOverride {
+    pub price_pay_amount: u128,
+    pub price_receive_amount: u128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct UserInfo {
     pub allocation: u128,
     pub spent: u128,
     pub received_amount: u128,
+    #[serde(default)]
+    pub price: Option<PriceOverride>, // replaces the sale price when set
+    #[serde(default)]
+    pub bought: u128, // paid by this address itself, with its allocation or an NFT's
 }
 
 impl UserInfo {
     pub fn available_allocation(&self) -> u128 {
-        self.allocation - self.spent
+        // FCFS buys can go past the allocation
+        self.allocation.saturating_sub(self.spent)
     }
 }
 
+// whether users may hand unspent allocation to other addresses
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TransferConfig {
+    pub enabled: bool,
+    pub whitelisted_only: bool, // only to addresses that already have an allocation
+}
+
+pub struct UserIndexes<'a> {
+    pub bought: MultiIndex<'a, u128, UserInfo, &'a Addr>,
+}
+
+impl<'a> IndexList<UserInfo> for UserIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<UserInfo>> + '_> {
+        let v: Vec<&dyn Index<UserInfo>> = vec![&self.bought];
+        Box::new(v.into_iter())
+    }
+}
+
+// first come first served phase, whitelisted users may buy up to `multiplier` times
+// their allocation from the pool of unspent allocations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FcfsConfig {
+    pub start: Scheduled,
+    pub multiplier: Decimal,
+}
+
+impl FcfsConfig {
+    pub fn is_active(&self, block: &BlockInfo) -> bool {
+        self.start.is_triggered(block)
+    }
+
+    pub fn cap(&self, allocation: u128) -> u128 {
+        Uint128::from(allocation).mul_floor(self.multiplier).u128()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocationRule {
+    Manual, // the operator sets allocations for registrants
+    Equal,
+    Weighted, // by the weight given at registration, up to the escrowed holding
+}
+
+// sign-ups ahead of the sale, turned into allocations once closed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RegistrationConfig {
+    pub start: Scheduled,
+    pub end: Expiration,
+    pub deposit: Option<Coin>,     // held until the sale ends
+    pub min_holding: Option<Coin>, // escrowed by registrants, held until the sale ends
+    pub rule: AllocationRule,
+    pub total_allocation: u128, // split between registrants, in the cap unit
+}
+
+impl RegistrationConfig {
+    pub fn is_open(&self, block: &BlockInfo) -> bool {
+        self.start.is_triggered(block) && !self.is_closed(block)
+    }
+
+    pub fn is_closed(&self, block: &BlockInfo) -> bool {
+        self.end.is_expired(block)
+    }
+
+    pub fn validate(&self, sale: &SaleConfig) -> Result<(), ContractError> {
+        // registration ends before the sale starts, when the two can be compared
+        let ordered = match (&self.start, &self.end, &sale.start) {
+            (_, Expiration::Never {}, _) => false,
+            (Scheduled::AtHeight(start), Expiration::AtHeight(end), Scheduled::AtHeight(sale)) => {
+                start <= end && end <= sale
+            }
+            (Scheduled::AtTime(start), Expiration::AtTime(end), Scheduled::AtTime(sale)) => {
+                start <= end && end <= sale
+            }
+            _ => true,
+        };
+        let deposit_ok = !self
+            .deposit
+            .as_ref()
+            .is_some_and(|coin| coin.amount.is_zero());
+        let split_ok = self.rule == AllocationRule::Manual || self.total_allocation > 0;
+        let weight_ok = self.rule != AllocationRule::Weighted
+            || self
+                .min_holding
+                .as_ref()
+                .is_some_and(|coin| !coin.amount.is_zero());
+        // the deposit and the escrowed holding are told apart by denom
+        let denoms_ok = match (&self.deposit, &self.min_holding) {
+            (Some(deposit), Some(holding)) => deposit.denom != holding.denom,
+            _ => true,
+        };
+        if !ordered || !deposit_ok || !split_ok || !weight_ok || !denoms_ok {
+            return Err(ContractError::InvalidRegistrationConfig {});
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct RegistrationData {
+    pub registrants: u64,
+    pub total_weight: u128,
+    pub allocated: u64,               // registrants turned into allocations
+    pub last_allocated: Option<Addr>, // where allocating continues
+    #[serde(default)]
+    pub deposits: u128, // held for registrants, in the deposit denom
+    #[serde(default)]
+    pub holdings: u128, // escrowed for registrants, in the min_holding denom
+}
+
+impl RegistrationData {
+    // allocation of a registrant under a split rule
+    pub fn share(&self, config: &RegistrationConfig, weight: u128) -> u128 {
+        match config.rule {
+            AllocationRule::Manual => 0,
+            AllocationRule::Equal => config.total_allocation / self.registrants as u128,
+            AllocationRule::Weighted => Uint128::from(config.total_allocation)
+                .multiply_ratio(weight, self.total_weight)
+                .u128(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Registration {
+    pub weight: u128,
+    pub deposit: Option<Coin>,    // cleared once returned
+    pub allocation: Option<u128>, // set once allocated
+    #[serde(default)]
+    pub holding: Option<Coin>, // escrowed with the deposit, cleared once returned
+}
+
+// after finalize buyers may give back unclaimed tokens for a share of what they paid, net of fees
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuranceConfig {
+    pub window: u64, // seconds after finalize
+    // paid for any return in the first `unconditional_window` seconds
+    pub unconditional_window: u64,
+    pub unconditional_share: Decimal,
+    // paid while the sale token trades below the buyer's price, pay_denom taken as USD
+    pub oracle: Option<OracleConfig>,
+    pub below_price_share: Decimal,
+}
+
+impl InsuranceConfig {
+    pub fn validate(&self, sale: &SaleConfig) -> Result<(), ContractError> {
+        // oracle prices are per whole token, sale prices only are with decimals set
+        let comparable = self.oracle.is_none() || (sale.pay_decimals > 0 && sale.sale_decimals > 0);
+        if self.unconditional_window > self.window
+            || self.unconditional_share > Decimal::one()
+            || self.below_price_share > Decimal::one()
+            || !comparable
+        {
+            return Err(ContractError::InvalidInsuranceConfig {});
+        }
+
+        Ok(())
+    }
+
+    // the raise stays reserved until the window after finalize is over
+    pub fn is_closed(&self, finalized_at: Option<Timestamp>, now: Timestamp) -> bool {
+        finalized_at.is_some_and(|finalized_at| now >= finalized_at.plus_seconds(self.window))
+    }
+
+    // largest share a buyer can get back
+    pub fn max_share(&self) -> Decimal {
+        match self.oracle {
+            Some(_) => self.unconditional_share.max(self.below_price_share),
+            None => self.unconditional_share,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeRecipient {
+    pub address: Addr,
+    pub label: String,
+    pub bps: u64, // share of raised funds (100% = 10000)
+}
+
+impl FeeRecipient {
+    pub fn fee(&self, amount: u128) -> u128 {
+        amount * self.bps as u128 / 10000
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct FeeBalance {
+    pub pending: u128,
+    pub settled: u128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferralRewardDenom {
+    Pay,
+    Sale,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ReferralConfig {
+    pub reward_bps: u64, // share of referred volume (100% = 10000)
+    pub reward_denom: ReferralRewardDenom,
+}
+
+impl ReferralConfig {
+    pub fn reward(&self, amount: u128) -> u128 {
+        amount * self.reward_bps as u128 / 10000
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct ReferralInfo {
+    pub volume: u128,       // referred pay amount
+    pub buys: u64,          // referred buys
+    pub token_reward: u128, // earned in sale tokens
+}
+
+pub struct ReferralIndexes<'a> {
+    pub volume: MultiIndex<'a, u128, ReferralInfo, &'a Addr>,
+}
+
+impl<'a> IndexList<ReferralInfo> for ReferralIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<ReferralInfo>> + '_> {
+        let v: Vec<&dyn Index<ReferralInfo>> = vec![&self.volume];
+        Box::new(v.into_iter())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct Raised {
+    pub total: u128,
+    pub withdrawn: u128,
+    #[serde(default)]
+    pub refunded: u128,
+    #[serde(default)]
+    pub contributed: u128, // sum of contributions still held for buyers
+    #[serde(default)]
+    pub contributed_net: u128, // sum of their net amounts
+    #[serde(default)]
+    pub pending_fees: u128,
+    #[serde(default)]
+    pub pending_rewards: u128, // pay denom referral rewards
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct Contribution {
+    pub paid: u128,
+    pub net: u128, // what stayed in the raise after fees and pay denom referral rewards
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Cancellation {
+    pub reason: String,
+    pub cancelled_at: Timestamp,
+    pub refunded_users: u64, // buyers with nothing left to refund
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct SaleData {
     pub total_spent: u128,
+    pub total_tokens_sold: u128,
+    pub referral_tokens: u128, // sale token rewards owed to referrers
+    pub total_claimed: u128,
+    pub finalized_at: Option<Timestamp>,
+    pub dust: u128, // pay_denom units that bought no tokens due to rounding
+    #[serde(default)]
+    pub buys: u64,
+    #[serde(default)]
+    pub total_allocated: u128, // sum of user allocations
+    #[serde(default)]
+    pub allocation_spent: u128, // sum of user spent, including spent set by the operator
+    #[serde(default)]
+    pub tokens_returned: u128, // given back through the insurance
+    #[serde(default)]
+    pub sold_out_at: Option<Timestamp>, // the sale ended there
+    #[serde(default)]
+    pub contributors: u64, // buyers with contributions
+}
+
+impl SaleData {
+    // unspent allocations released to the FCFS phase, limited by the supply left
+    pub fn fcfs_pool(&self, max_supply: u128) -> u128 {
+        self.total_allocated
+            .saturating_sub(self.allocation_spent)
+            .min(max_supply.saturating_sub(self.total_spent))
+    }
+
+    // sale tokens owed to buyers and referrers
+    pub fn tokens_owed(&self) -> u128 {
+        self.total_tokens_sold + self.referral_tokens - self.tokens_returned
+    }
 }
 
 pub const STATE: Item<State> = Item::new("state");
+pub const PENDING_OWNER: Item<PendingOwner> = Item::new("pending_owner");
+pub const ROLES: Item<Roles> = Item::new("roles");
 pub const SALE_CONFIG: Item<SaleConfig> = Item::new("sale");
-pub const USER_LIST: Map<&Addr, UserInfo> = Map::new("users");
+pub const USER_LIST: IndexedMap<&Addr, UserInfo, UserIndexes> = IndexedMap::new(
+    "users",
+    UserIndexes {
+        bought: MultiIndex::new(|_, user| user.bought, "users", "users__bought"),
+    },
+);
 pub const SALE_DATA: Item<SaleData> = Item::new("sale_data");
+// set when the sale token is a token factory denom created by the contract
+pub const TOKEN_FACTORY_DENOM: Item<String> = Item::new("token_factory_denom");
+pub const CLAIMED: Map<&Addr, u128> = Map::new("claimed");
+// pay denom -> raised funds
+pub const RAISED: Map<&str, Raised> = Map::new("raised");
+// (buyer, denom) -> amount paid, returned if the sale is cancelled
+pub const CONTRIBUTIONS: Map<(&Addr, &str), Contribution> = Map::new("contributions");
+// set once the sale is cancelled, for good
+pub const CANCELLATION: Item<Cancellation> = Item::new("cancellation");
+pub const FEE_RECIPIENTS: Item<Vec<FeeRecipient>> = Item::new("fee_recipients");
+// (recipient, denom) -> fee balance
+pub const FEES: Map<(&Addr, &str), FeeBalance> = Map::new("fees");
+pub const REFERRAL_CONFIG: Item<ReferralConfig> = Item::new("referral_config");
+pub const BENEFICIARY_CONFIG: Item<BeneficiaryConfig> = Item::new("beneficiary_config");
+pub const ANTI_BOT_CONFIG: Item<AntiBotConfig> = Item::new("anti_bot_config");
+pub const TRANSFER_CONFIG: Item<TransferConfig> = Item::new("transfer_config");
+// cw721 collection whose tokens carry allocations
+pub const ALLOCATION_NFT: Item<Addr> = Item::new("allocation_nft");
+// token id -> allocation spendable by the current holder
+pub const NFT_ALLOCATIONS: Map<&str, UserInfo> = Map::new("nft_allocations");
+// no FCFS phase when unset
+pub const FCFS_CONFIG: Item<FcfsConfig> = Item::new("fcfs_config");
+// no registration phase when unset
+pub const REGISTRATION_CONFIG: Item<RegistrationConfig> = Item::new("registration_config");
+pub const REGISTRATION_DATA: Item<RegistrationData> = Item::new("registration_data");
+pub const REGISTRATIONS: Map<&Addr, Registration> = Map::new("registrations");
+// no insurance when unset
+pub const INSURANCE_CONFIG: Item<InsuranceConfig> = Item::new("insurance_config");
+pub const BUYER_ACTIVITY: Map<&Addr, BuyerActivity> = Map::new("buyer_activity");
+pub const BLOCK_VOLUME: Item<BlockVolume> = Item::new("block_volume");
+pub const REFERRALS: IndexedMap<&Addr, ReferralInfo, ReferralIndexes> = IndexedMap::new(
+    "referrals",
+    ReferralIndexes {
+        volume: MultiIndex::new(|_, info| info.volume, "referrals", "referrals__volume"),
+    },
+);
+// (referrer, denom) -> reward earned in pay denom
+pub const REFERRAL_REWARDS: Map<(&Addr, &str), FeeBalance> = Map::new("referral_rewards");
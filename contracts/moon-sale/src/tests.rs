@@ -1,31 +1,45 @@
 use cosmwasm_std::{
     coins, from_json,
-    testing::{mock_dependencies, mock_env, mock_info},
+    testing::{mock_env, mock_info},
     Addr, Timestamp,
 };
 use cw_multi_test::BankKeeper;
+use cw_utils::{Expiration, Scheduled};
+use injective_cosmwasm::inj_mock_deps;
 
 use crate::{
     contract::{execute, instantiate, query},
-    msg::{ExecuteMsg, GetSaleResponse, InstantiateMsg, QueryMsg, UserInfoReq, UserInfoRes},
-    state::SaleConfig,
+    msg::{
+        ExecuteMsg, GetRolesResponse, GetSaleResponse, InstantiateMsg, QueryMsg, UserInfoReq,
+        UserInfoRes,
+    },
+    state::{CapUnit, OracleConfig, PayDenom, PendingOwner, SaleConfig, State},
     ContractError,
 };
 
 #[test]
 fn proper_initialization() {
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(0),
-        end_time: Timestamp::from_seconds(0),
+        start: Scheduled::AtTime(Timestamp::from_seconds(0)),
+        end: Expiration::AtTime(Timestamp::from_seconds(0)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+    let msg = InstantiateMsg {
+        sale: sale.clone(),
+        token_factory: None,
     };
-    let msg = InstantiateMsg { sale: sale.clone() };
     let info = mock_info("creator", &coins(1000, "earth"));
 
     // we can just call .unwrap() to assert this was a success
@@ -40,29 +54,47 @@ fn proper_initialization() {
 
 #[test]
 pub fn update_sale() {
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(0);
+
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(0),
-        end_time: Timestamp::from_seconds(0),
+        start: Scheduled::AtTime(Timestamp::from_seconds(1)),
+        end: Expiration::AtTime(Timestamp::from_seconds(1)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     let info = mock_info("creator", &coins(2, "token"));
-    let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), instantiate_msg).unwrap();
+    let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     let new_sale = SaleConfig {
-        start_time: Timestamp::from_seconds(1),
-        end_time: Timestamp::from_seconds(1),
+        start: Scheduled::AtTime(Timestamp::from_seconds(1)),
+        end: Expiration::AtTime(Timestamp::from_seconds(1)),
         pay_denom: "usdt2".to_string(),
         sale_denom: Some("moon2".to_string()),
         max_supply: 2000000000000000000000000000,
         price_pay_amount: 3000000000000000000,
         price_receive_amount: 3000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     let msg = ExecuteMsg::UpdateSale {
@@ -71,31 +103,164 @@ pub fn update_sale() {
 
     // Prevent unauthorized update
     let unauth_info = mock_info("anyone", &[]);
-    let err = execute(deps.as_mut(), mock_env(), unauth_info, msg.clone()).unwrap_err();
+    let err = execute(deps.as_mut(), env.clone(), unauth_info, msg.clone()).unwrap_err();
     assert_eq!(err, ContractError::Unauthorized {});
     // Unauthroized - end
 
-    let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-    let sale_res = query(deps.as_ref(), mock_env(), QueryMsg::GetSale {}).unwrap();
+    let sale_res = query(deps.as_ref(), env, QueryMsg::GetSale {}).unwrap();
+    let sale_value: GetSaleResponse = from_json(sale_res).unwrap();
+    assert_eq!(new_sale, sale_value.sale_config);
+}
+
+#[test]
+pub fn update_sale_after_start() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let mut deps = inj_mock_deps(|_| {});
+    let info = mock_info(owner.as_str(), &[]);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(5);
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
+    };
+
+    // instantiate
+    let instantiate_msg = InstantiateMsg {
+        sale: sale.clone(),
+        token_factory: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
+
+    // buy
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        }],
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(user.as_str(), &coins(40, "uusd")),
+        ExecuteMsg::Buy {
+            referrer: None,
+            partial_fill: None,
+            min_tokens_out: None,
+            beneficiary: None,
+            token_id: None,
+        },
+    )
+    .unwrap();
+
+    // price is locked
+    let msg = ExecuteMsg::UpdateSale {
+        sale: SaleConfig {
+            price_receive_amount: 3,
+            extra_pay_denoms: vec![],
+            pay_decimals: 0,
+            sale_decimals: 0,
+            ..sale.clone()
+        },
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    assert_eq!(Err(ContractError::PriceLocked {}), res);
+
+    // pay denom is locked
+    let msg = ExecuteMsg::UpdateSale {
+        sale: SaleConfig {
+            pay_denom: "usdt".to_string(),
+            ..sale.clone()
+        },
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    assert_eq!(Err(ContractError::PayDenomLocked {}), res);
+
+    // moving start back doesn't unlock the price once bought
+    let mut before_start = env.clone();
+    before_start.block.time = Timestamp::from_seconds(1);
+    let msg = ExecuteMsg::UpdateSale {
+        sale: SaleConfig {
+            price_pay_amount: 2,
+            ..sale.clone()
+        },
+    };
+    let res = execute(deps.as_mut(), before_start, info.clone(), msg);
+    assert_eq!(Err(ContractError::PriceLocked {}), res);
+
+    // max supply can't go below total spent
+    let msg = ExecuteMsg::UpdateSale {
+        sale: SaleConfig {
+            max_supply: 39,
+            ..sale.clone()
+        },
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    assert_eq!(
+        Err(ContractError::MaxSupplyBelowSpent {
+            max_supply: 39,
+            total_spent: 40
+        }),
+        res
+    );
+
+    // other fields can still change
+    let new_sale = SaleConfig {
+        end: Expiration::AtTime(Timestamp::from_seconds(20)),
+        max_supply: 40,
+        ..sale
+    };
+    let msg = ExecuteMsg::UpdateSale {
+        sale: new_sale.clone(),
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let sale_res = query(deps.as_ref(), env, QueryMsg::GetSale {}).unwrap();
     let sale_value: GetSaleResponse = from_json(sale_res).unwrap();
     assert_eq!(new_sale, sale_value.sale_config);
 }
 
 #[test]
 pub fn set_user_list() {
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(0),
-        end_time: Timestamp::from_seconds(0),
+        start: Scheduled::AtTime(Timestamp::from_seconds(0)),
+        end: Expiration::AtTime(Timestamp::from_seconds(0)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     let info = mock_info("creator", &coins(2, "token"));
     let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), instantiate_msg).unwrap();
 
@@ -104,11 +269,13 @@ pub fn set_user_list() {
             address: "addr1".to_string(),
             allocation: 100,
             spent: 0,
+            price: None,
         },
         UserInfoReq {
             address: "addr2".to_string(),
             allocation: 200,
             spent: 50,
+            price: None,
         },
     ];
 
@@ -169,6 +336,8 @@ pub fn set_user_list() {
             allocation: 0,
             spent: 0,
             received_amount: 0,
+            price: None,
+            bought: 0,
         },
         user_value
     );
@@ -184,18 +353,129 @@ pub fn set_user_list() {
                 allocation: v.allocation,
                 spent: v.spent,
                 received_amount: 0,
+                price: None,
+                bought: 0,
             })
             .collect::<Vec<UserInfoRes>>(),
         users_value
     );
 }
 
+#[test]
+pub fn set_user_list_keeps_buy_history() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+
+    let mut deps = inj_mock_deps(|_| {});
+    let info = mock_info(owner.as_str(), &[]);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(5);
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    // instantiate
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
+
+    // buy
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        }],
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(user.as_str(), &coins(40, "uusd")),
+        ExecuteMsg::Buy {
+            referrer: None,
+            partial_fill: None,
+            min_tokens_out: None,
+            beneficiary: None,
+            token_id: None,
+        },
+    )
+    .unwrap();
+
+    // allocation can't go below spent
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 30,
+            spent: 0,
+            price: None,
+        }],
+    };
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    assert_eq!(
+        Err(ContractError::AllocationBelowSpent {
+            address: user.to_string(),
+            allocation: 30,
+            spent: 40
+        }),
+        res
+    );
+
+    // spent and received amount are kept on update
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 200,
+            spent: 0,
+            price: None,
+        }],
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let user_res = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::GetUserInfo {
+            address: user.to_string(),
+        },
+    )
+    .unwrap();
+    let user_value: UserInfoRes = from_json(user_res).unwrap();
+    assert_eq!(
+        UserInfoRes {
+            address: user.to_string(),
+            allocation: 200,
+            spent: 40,
+            received_amount: 80,
+            price: None,
+            bought: 40,
+        },
+        user_value
+    );
+}
+
 #[test]
 pub fn buy_success() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(5);
@@ -205,17 +485,26 @@ pub fn buy_success() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -223,6 +512,7 @@ pub fn buy_success() {
         address: user.to_string(),
         allocation: 100,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -230,7 +520,13 @@ pub fn buy_success() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(3, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let sale_res = query(deps.as_ref(), env.clone(), QueryMsg::GetSale {}).unwrap();
@@ -251,6 +547,8 @@ pub fn buy_success() {
             allocation: 100,
             spent: 3,
             received_amount: 3,
+            price: None,
+            bought: 3,
         },
         user_value
     );
@@ -261,7 +559,7 @@ pub fn buy_success_with_different_price() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(5);
@@ -271,17 +569,26 @@ pub fn buy_success_with_different_price() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_receive_amount: 2,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
         price_pay_amount: 1,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     let _res = instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -289,6 +596,7 @@ pub fn buy_success_with_different_price() {
         address: user.to_string(),
         allocation: 100,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -296,7 +604,13 @@ pub fn buy_success_with_different_price() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(4, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let sale_res = query(deps.as_ref(), env.clone(), QueryMsg::GetSale {}).unwrap();
@@ -317,6 +631,8 @@ pub fn buy_success_with_different_price() {
             allocation: 100,
             spent: 4,
             received_amount: 8,
+            price: None,
+            bought: 4,
         },
         user_value
     );
@@ -327,7 +643,7 @@ pub fn buy_without_user_allocation() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(5);
@@ -337,17 +653,26 @@ pub fn buy_without_user_allocation() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -355,6 +680,7 @@ pub fn buy_without_user_allocation() {
         address: user.to_string(),
         allocation: 0,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -362,7 +688,13 @@ pub fn buy_without_user_allocation() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(1000, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg);
     assert_eq!(Err(ContractError::NotParticipating {}), _res);
 }
@@ -372,7 +704,7 @@ pub fn buy_without_user_available_allocation() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(5);
@@ -382,17 +714,26 @@ pub fn buy_without_user_available_allocation() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 1000000000000000000000000000,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -400,6 +741,7 @@ pub fn buy_without_user_available_allocation() {
         address: user.to_string(),
         allocation: 100,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -407,7 +749,13 @@ pub fn buy_without_user_available_allocation() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(1000, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg);
     assert_eq!(
         Err(ContractError::UserAllocationExceeded {
@@ -423,7 +771,7 @@ pub fn buy_without_sale_available_allocation() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(5);
@@ -433,17 +781,26 @@ pub fn buy_without_sale_available_allocation() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -451,6 +808,7 @@ pub fn buy_without_sale_available_allocation() {
         address: user.to_string(),
         allocation: 1000,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -458,7 +816,13 @@ pub fn buy_without_sale_available_allocation() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(1000, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg);
     assert_eq!(
         Err(ContractError::SaleAllocationExceeded {
@@ -474,7 +838,7 @@ pub fn buy_with_sale_not_active() {
     let user = Addr::unchecked("user");
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let mut env = mock_env();
     env.block.time = Timestamp::from_seconds(0);
@@ -484,17 +848,26 @@ pub fn buy_with_sale_not_active() {
         .unwrap();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     // set users
@@ -502,6 +875,7 @@ pub fn buy_with_sale_not_active() {
         address: user.to_string(),
         allocation: 1000,
         spent: 0,
+        price: None,
     }];
     let msg = ExecuteMsg::SetUserList {
         users: users.clone(),
@@ -509,37 +883,252 @@ pub fn buy_with_sale_not_active() {
     let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     let info = mock_info(user.as_str(), &coins(1000, "uusd"));
-    let msg = ExecuteMsg::Buy {};
+    let msg = ExecuteMsg::Buy {
+        referrer: None,
+        partial_fill: None,
+        min_tokens_out: None,
+        beneficiary: None,
+        token_id: None,
+    };
     let _res = execute(deps.as_mut(), env.clone(), info, msg);
     assert_eq!(Err(ContractError::SaleNotActive {}), _res);
 }
 
+#[test]
+pub fn buy_while_paused() {
+    let user = Addr::unchecked("user");
+    let owner = Addr::unchecked("owner");
+    let pauser = Addr::unchecked("pauser");
+
+    let mut deps = inj_mock_deps(|_| {});
+    let info = mock_info(owner.as_str(), &[]);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(5);
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 1000,
+        price_pay_amount: 1,
+        price_receive_amount: 1,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    // instantiate
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
+
+    let msg = ExecuteMsg::UpdateRoles {
+        operator: None,
+        treasury: None,
+        pauser: Some(pauser.to_string()),
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // set users
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: user.to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        }],
+    };
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    // only pauser
+    let msg = ExecuteMsg::Pause {
+        reason: "investigating".to_string(),
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    assert_eq!(Err(ContractError::Unauthorized {}), _res);
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(pauser.as_str(), &[]),
+        msg,
+    )
+    .unwrap();
+
+    let state_res = query(deps.as_ref(), env.clone(), QueryMsg::GetState {}).unwrap();
+    let state_value: State = from_json(state_res).unwrap();
+    assert_eq!(Some("investigating".to_string()), state_value.pause_reason);
+
+    let buy_info = mock_info(user.as_str(), &coins(10, "uusd"));
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        buy_info.clone(),
+        ExecuteMsg::Buy {
+            referrer: None,
+            partial_fill: None,
+            min_tokens_out: None,
+            beneficiary: None,
+            token_id: None,
+        },
+    );
+    assert_eq!(
+        Err(ContractError::Paused {
+            reason: "investigating".to_string()
+        }),
+        _res
+    );
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(pauser.as_str(), &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(pauser.as_str(), &[]),
+        ExecuteMsg::Unpause {},
+    );
+    assert_eq!(Err(ContractError::NotPaused {}), _res);
+
+    let _res = execute(
+        deps.as_mut(),
+        env,
+        buy_info,
+        ExecuteMsg::Buy {
+            referrer: None,
+            partial_fill: None,
+            min_tokens_out: None,
+            beneficiary: None,
+            token_id: None,
+        },
+    );
+    assert!(_res.is_ok());
+}
+
+#[test]
+pub fn calculate_token_received_amount_with_oracle() {
+    let owner = Addr::unchecked("owner");
+
+    // the mock querier prices every pyth feed at 1, published at 1s
+    let mut deps = inj_mock_deps(|_| {});
+    let info = mock_info(owner.as_str(), &[]);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(2);
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10,
+        price_pay_amount: 1,
+        price_receive_amount: 2,
+        extra_pay_denoms: vec![PayDenom {
+            denom: "inj".to_string(),
+            price_pay_amount: 0,
+            price_receive_amount: 0,
+            decimals: 18,
+            oracle: Some(OracleConfig {
+                price_id: "inj_usd".to_string(),
+                max_staleness: 60,
+                max_deviation_bps: 100,
+            }),
+        }],
+        pay_decimals: 6,
+        sale_decimals: 6,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
+    };
+
+    // instantiate
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info, instantiate_msg).unwrap();
+
+    let amount = query(
+        deps.as_ref(),
+        env.clone(),
+        QueryMsg::GetReceivedAmount {
+            pay_amount: 1000000000000000000,
+            denom: Some("inj".to_string()),
+            address: None,
+        },
+    )
+    .unwrap();
+    let amount_value: u128 = from_json(amount).unwrap();
+
+    assert_eq!(2000000, amount_value);
+
+    // fixed price denoms don't need the oracle
+    let amount = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::GetReceivedAmount {
+            pay_amount: 10,
+            denom: None,
+            address: None,
+        },
+    )
+    .unwrap();
+    let amount_value: u128 = from_json(amount).unwrap();
+
+    assert_eq!(20, amount_value);
+}
+
 #[test]
 pub fn calculate_token_received_amount() {
     let owner = Addr::unchecked("owner");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
-    let env = mock_env();
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(0);
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
     let amount = query(
         deps.as_ref(),
         env.clone(),
-        QueryMsg::GetReceivedAmount { pay_amount: 218 },
+        QueryMsg::GetReceivedAmount {
+            pay_amount: 218,
+            denom: None,
+            address: None,
+        },
     )
     .unwrap();
     let amount_value: u128 = from_json(amount).unwrap();
@@ -548,17 +1137,23 @@ pub fn calculate_token_received_amount() {
 
     let _res = execute(
         deps.as_mut(),
-        mock_env(),
+        env.clone(),
         info.clone(),
         ExecuteMsg::UpdateSale {
             sale: SaleConfig {
-                start_time: Timestamp::from_seconds(3),
-                end_time: Timestamp::from_seconds(10),
+                start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+                end: Expiration::AtTime(Timestamp::from_seconds(10)),
                 pay_denom: "uusd".to_string(),
                 sale_denom: Some("moon".to_string()),
                 max_supply: 1000000000000000000000000000,
                 price_pay_amount: 2,
                 price_receive_amount: 129874,
+                extra_pay_denoms: vec![],
+                pay_decimals: 0,
+                sale_decimals: 0,
+                cap_unit: CapUnit::Pay,
+                anti_sniping: None,
+                allow_oversubscription: false,
             },
         },
     )
@@ -567,7 +1162,11 @@ pub fn calculate_token_received_amount() {
     let amount = query(
         deps.as_ref(),
         env.clone(),
-        QueryMsg::GetReceivedAmount { pay_amount: 1321 },
+        QueryMsg::GetReceivedAmount {
+            pay_amount: 1321,
+            denom: None,
+            address: None,
+        },
     )
     .unwrap();
     let amount_value: u128 = from_json(amount).unwrap();
@@ -575,17 +1174,23 @@ pub fn calculate_token_received_amount() {
 
     let _res = execute(
         deps.as_mut(),
-        mock_env(),
+        env.clone(),
         info,
         ExecuteMsg::UpdateSale {
             sale: SaleConfig {
-                start_time: Timestamp::from_seconds(3),
-                end_time: Timestamp::from_seconds(10),
+                start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+                end: Expiration::AtTime(Timestamp::from_seconds(10)),
                 pay_denom: "uusd".to_string(),
                 sale_denom: Some("moon".to_string()),
                 max_supply: 1000000000000000000000000000,
                 price_pay_amount: 3,
                 price_receive_amount: 2,
+                extra_pay_denoms: vec![],
+                pay_decimals: 0,
+                sale_decimals: 0,
+                cap_unit: CapUnit::Pay,
+                anti_sniping: None,
+                allow_oversubscription: false,
             },
         },
     )
@@ -594,7 +1199,11 @@ pub fn calculate_token_received_amount() {
     let amount = query(
         deps.as_ref(),
         env.clone(),
-        QueryMsg::GetReceivedAmount { pay_amount: 1000 },
+        QueryMsg::GetReceivedAmount {
+            pay_amount: 1000,
+            denom: None,
+            address: None,
+        },
     )
     .unwrap();
     let amount_value: u128 = from_json(amount).unwrap();
@@ -606,51 +1215,206 @@ pub fn change_ownership() {
     let owner = Addr::unchecked("owner");
     let any = Addr::unchecked("any");
 
-    let mut deps = mock_dependencies();
+    let mut deps = inj_mock_deps(|_| {});
     let info = mock_info(owner.as_str(), &[]);
     let env = mock_env();
 
     let sale: SaleConfig = SaleConfig {
-        start_time: Timestamp::from_seconds(3),
-        end_time: Timestamp::from_seconds(10),
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
         pay_denom: "uusd".to_string(),
         sale_denom: Some("moon".to_string()),
         max_supply: 10,
         price_pay_amount: 1000000000000000000,
         price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: false,
     };
 
     // instantiate
-    let instantiate_msg = InstantiateMsg { sale };
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
     instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
 
+    // nothing to accept yet
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(any.as_str(), &[]),
+        ExecuteMsg::AcceptOwnership {},
+    );
+    assert_eq!(Err(ContractError::NoPendingOwner {}), _res);
+
     execute(
         deps.as_mut(),
-        mock_env(),
+        env.clone(),
         info.clone(),
         ExecuteMsg::TransferOwnership {
             address: any.to_string(),
+            expiry: None,
         },
     )
     .unwrap();
 
+    // owner doesn't change until accepted
+    let roles_res = query(deps.as_ref(), env.clone(), QueryMsg::GetRoles {}).unwrap();
+    let roles_value: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(owner, roles_value.owner);
+    assert_eq!(
+        Some(PendingOwner {
+            address: any.clone(),
+            expires: Expiration::Never {}
+        }),
+        roles_value.pending_owner
+    );
+
+    // only pending owner can accept
     let _res = execute(
         deps.as_mut(),
-        mock_env(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::AcceptOwnership {},
+    );
+    assert_eq!(Err(ContractError::Unauthorized {}), _res);
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(any.as_str(), &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap();
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
         info.clone(),
         ExecuteMsg::TransferOwnership {
             address: any.to_string(),
+            expiry: None,
         },
     );
     assert_eq!(Err(ContractError::Unauthorized {}), _res);
 
-    let _res = execute(
+    let roles_res = query(deps.as_ref(), env.clone(), QueryMsg::GetRoles {}).unwrap();
+    let roles_value: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(any, roles_value.owner);
+    assert_eq!(None, roles_value.pending_owner);
+
+    // expired transfer can't be accepted
+    execute(
         deps.as_mut(),
-        mock_env(),
+        env.clone(),
         mock_info(any.as_str(), &[]),
         ExecuteMsg::TransferOwnership {
             address: owner.to_string(),
+            expiry: Some(Expiration::AtTime(env.block.time.plus_seconds(10))),
         },
+    )
+    .unwrap();
+
+    let mut later = env.clone();
+    later.block.time = env.block.time.plus_seconds(10);
+    let _res = execute(
+        deps.as_mut(),
+        later,
+        info.clone(),
+        ExecuteMsg::AcceptOwnership {},
     );
+    assert_eq!(Err(ContractError::OwnershipTransferExpired {}), _res);
+
+    let _res = execute(deps.as_mut(), env, info, ExecuteMsg::AcceptOwnership {});
+    assert!(_res.is_ok());
+}
+
+#[test]
+pub fn update_roles() {
+    let owner = Addr::unchecked("owner");
+    let operator = Addr::unchecked("operator");
+    let treasury = Addr::unchecked("treasury");
+
+    let mut deps = inj_mock_deps(|_| {});
+    let info = mock_info(owner.as_str(), &[]);
+    let env = mock_env();
+
+    let sale: SaleConfig = SaleConfig {
+        start: Scheduled::AtTime(Timestamp::from_seconds(3)),
+        end: Expiration::AtTime(Timestamp::from_seconds(10)),
+        pay_denom: "uusd".to_string(),
+        sale_denom: Some("moon".to_string()),
+        max_supply: 10,
+        price_pay_amount: 1000000000000000000,
+        price_receive_amount: 1000000000000000000,
+        extra_pay_denoms: vec![],
+        pay_decimals: 0,
+        sale_decimals: 0,
+        cap_unit: CapUnit::Pay,
+        anti_sniping: None,
+        allow_oversubscription: true,
+    };
+
+    // instantiate
+    let instantiate_msg = InstantiateMsg {
+        sale,
+        token_factory: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info.clone(), instantiate_msg).unwrap();
+
+    // roles start with the owner
+    let roles_res = query(deps.as_ref(), env.clone(), QueryMsg::GetRoles {}).unwrap();
+    let roles_value: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(
+        GetRolesResponse {
+            owner: owner.clone(),
+            pending_owner: None,
+            operator: owner.clone(),
+            treasury: owner.clone(),
+            pauser: owner.clone(),
+        },
+        roles_value
+    );
+
+    let msg = ExecuteMsg::UpdateRoles {
+        operator: Some(operator.to_string()),
+        treasury: Some(treasury.to_string()),
+        pauser: None,
+    };
+
+    // only owner
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(operator.as_str(), &[]),
+        msg.clone(),
+    );
+    assert_eq!(Err(ContractError::Unauthorized {}), _res);
+
+    execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+    let roles_res = query(deps.as_ref(), env.clone(), QueryMsg::GetRoles {}).unwrap();
+    let roles_value: GetRolesResponse = from_json(roles_res).unwrap();
+    assert_eq!(operator, roles_value.operator);
+    assert_eq!(treasury, roles_value.treasury);
+    assert_eq!(owner, roles_value.pauser);
+
+    // whitelist is managed by the operator
+    let msg = ExecuteMsg::SetUserList {
+        users: vec![UserInfoReq {
+            address: "addr1".to_string(),
+            allocation: 100,
+            spent: 0,
+            price: None,
+        }],
+    };
+    let _res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    assert_eq!(Err(ContractError::Unauthorized {}), _res);
+
+    let _res = execute(deps.as_mut(), env, mock_info(operator.as_str(), &[]), msg);
     assert!(_res.is_ok());
 }
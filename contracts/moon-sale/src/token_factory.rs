@@ -0,0 +1,37 @@
+use cosmwasm_std::{Addr, CosmosMsg};
+use injective_cosmwasm::InjectiveMsgWrapper;
+use prost::Message;
+
+pub const MSG_CHANGE_ADMIN: &str = "/injective.tokenfactory.v1beta1.MsgChangeAdmin";
+
+// not exposed by injective-cosmwasm, sent as a stargate message instead
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgChangeAdmin {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, tag = "2")]
+    pub denom: String,
+    #[prost(string, tag = "3")]
+    pub new_admin: String,
+}
+
+pub fn factory_denom(contract: &Addr, subdenom: &str) -> String {
+    format!("factory/{}/{}", contract, subdenom)
+}
+
+pub fn change_admin_msg(
+    sender: &Addr,
+    denom: &str,
+    new_admin: &Addr,
+) -> CosmosMsg<InjectiveMsgWrapper> {
+    CosmosMsg::Stargate {
+        type_url: MSG_CHANGE_ADMIN.to_string(),
+        value: MsgChangeAdmin {
+            sender: sender.to_string(),
+            denom: denom.to_string(),
+            new_admin: new_admin.to_string(),
+        }
+        .encode_to_vec()
+        .into(),
+    }
+}